@@ -0,0 +1,130 @@
+use std::io::{self, Read};
+use std::process::{Child, Command, Stdio};
+use std::sync::Arc;
+
+use log::{debug, warn};
+use rusty_razer_tray::openrazer::{Device, Frame, Rgb};
+use tokio::sync::watch;
+
+/// Level readings only need a rough waveform, so capture at a low rate in mono.
+const SAMPLE_RATE: u32 = 8_000;
+/// How many level readings, and so frames at most, are taken per second.
+const FRAMES_PER_SECOND: u32 = 25;
+/// Music rarely gets above a third of full scale RMS, so readings are boosted before use.
+const GAIN: f32 = 3.0;
+/// How much of the previous level is kept per frame, so the keys fade out instead of flickering.
+const DECAY: f32 = 0.85;
+/// Razer green, scaled by the level.
+const COLOR: Rgb = Rgb { r: 0x44, g: 0xd6, b: 0x2c };
+
+/// Lights a keyboard's matrix with the loudness of whatever the system is playing. Audio comes
+/// from `parec` recording the default output's monitor, which works on PulseAudio and on
+/// PipeWire through pipewire-pulse. Dropping it stops the capture, and the keys go dark.
+pub struct Visualizer {
+    capture: Child,
+}
+
+impl Visualizer {
+    pub fn start(device: Arc<Device>) -> io::Result<Visualizer> {
+        let mut child = Command::new("parec")
+            .args([
+                "--device=@DEFAULT_MONITOR@",
+                "--format=s16le",
+                "--channels=1",
+                "--raw",
+                &format!("--rate={SAMPLE_RATE}"),
+                &format!("--latency-msec={}", 1000 / FRAMES_PER_SECOND),
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("parec: {err}")))?;
+        let Some(stdout) = child.stdout.take() else {
+            let _ = child.kill();
+            return Err(io::Error::other("parec: no output pipe"));
+        };
+        let (levels, readings) = watch::channel(0.0);
+        std::thread::spawn(move || read_levels(stdout, levels));
+        tokio::spawn(render(device, readings));
+        Ok(Visualizer { capture: child })
+    }
+}
+
+impl Drop for Visualizer {
+    // Ending the capture closes the pipe, which in turn ends the reader and the render task.
+    fn drop(&mut self) {
+        let _ = self.capture.kill();
+        let _ = self.capture.wait();
+    }
+}
+
+fn read_levels(mut stdout: impl Read, levels: watch::Sender<f32>) {
+    let mut chunk = vec![0u8; (SAMPLE_RATE / FRAMES_PER_SECOND * 2) as usize];
+    while stdout.read_exact(&mut chunk).is_ok() {
+        let samples: Vec<i16> = chunk
+            .chunks_exact(2)
+            .map(|pair| i16::from_le_bytes([pair[0], pair[1]]))
+            .collect();
+        // Sending only fails once the render task is gone, which means nobody is listening.
+        if levels.send(rms(&samples)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Root mean square of `samples`, from 0 for silence to 1 for a full-scale square wave.
+fn rms(samples: &[i16]) -> f32 {
+    if samples.is_empty() {
+        return 0.0;
+    }
+    let sum: f64 = samples
+        .iter()
+        .map(|&sample| (f64::from(sample) / f64::from(i16::MAX)).powi(2))
+        .sum();
+    (sum / samples.len() as f64).sqrt() as f32
+}
+
+fn scale(color: Rgb, level: f32) -> Rgb {
+    let channel = |value: u8| (f32::from(value) * level).round() as u8;
+    Rgb {
+        r: channel(color.r),
+        g: channel(color.g),
+        b: channel(color.b),
+    }
+}
+
+async fn render(device: Arc<Device>, mut readings: watch::Receiver<f32>) {
+    let dims = match device.get_matrix_dimensions().await {
+        Ok(dims) => dims,
+        Err(err) => {
+            warn!("Audio visualizer can't read the matrix size: {err}");
+            return;
+        }
+    };
+    let mut shown = Frame::new(dims);
+    if let Err(err) = device.set_matrix(&shown).await {
+        warn!("Audio visualizer failed to switch to custom lighting: {err}");
+        return;
+    }
+    let mut level = 0.0f32;
+    while readings.changed().await.is_ok() {
+        let reading = (*readings.borrow_and_update() * GAIN).min(1.0);
+        level = reading.max(level * DECAY);
+        let mut next = Frame::new(dims);
+        let color = scale(COLOR, level);
+        for row in 0..dims.rows {
+            for column in 0..dims.columns {
+                next.set(row, column, color);
+            }
+        }
+        if let Err(err) = device.set_matrix_diff(&shown, &next).await {
+            warn!("Audio visualizer stopped: {err}");
+            return;
+        }
+        shown = next;
+    }
+    debug!("Audio capture ended; turning the keys off");
+    if let Err(err) = device.set_matrix(&Frame::new(dims)).await {
+        warn!("Failed to clear the keyboard after the audio visualizer: {err}");
+    }
+}
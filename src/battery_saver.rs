@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use log::{info, warn};
+use rusty_razer_tray::openrazer::{BatterySummary, Device, LedId};
+
+use crate::notifications;
+
+/// Used when a device doesn't report its own low-battery threshold.
+const DEFAULT_THRESHOLD: u8 = 10;
+/// OpenRazer clamps low-battery thresholds to 5-25%, so above this no device needs asking.
+const MAX_THRESHOLD: u8 = 25;
+
+/// Turns the lighting off on the configured devices once their battery drops below the device's
+/// low-battery threshold. Restoring the previous effect isn't attempted; `dimmed` remembers which
+/// devices were tried, whether or not it worked, so each discharge tries and warns at most once,
+/// until the device is charged.
+pub async fn apply(
+    devices: &[Arc<Device>],
+    summaries: &[BatterySummary],
+    serials: &HashSet<String>,
+    dimmed: &mut HashSet<String>,
+) {
+    for summary in summaries.iter().filter(|summary| serials.contains(&summary.serial)) {
+        if summary.charging {
+            dimmed.remove(&summary.serial);
+            continue;
+        }
+        if dimmed.contains(&summary.serial) {
+            continue;
+        }
+        if summary.percent >= MAX_THRESHOLD {
+            continue;
+        }
+        let Some(device) = devices
+            .iter()
+            .find(|device| device.serial_from_path() == Some(summary.serial.as_str()))
+        else {
+            continue;
+        };
+        let threshold = if device.has_feature("low_battery_threshold") {
+            device.get_low_battery_threshold().await.unwrap_or(DEFAULT_THRESHOLD)
+        } else {
+            DEFAULT_THRESHOLD
+        };
+        if summary.percent >= threshold {
+            continue;
+        }
+        dimmed.insert(summary.serial.clone());
+        if let Err(err) = device.set_none(LedId::Unspecified).await {
+            warn!("Failed to turn off lighting on {}: {err}", summary.serial);
+            continue;
+        }
+        info!("Turned off lighting on {} at {}% battery", summary.serial, summary.percent);
+        notifications::send(
+            "battery-low",
+            &format!("{} battery low", summary.name),
+            &format!("Lighting turned off at {}% to save battery", summary.percent),
+        )
+        .await;
+    }
+}
@@ -0,0 +1,60 @@
+use std::env;
+
+#[derive(Debug, Default)]
+pub struct Args {
+    pub install_service: bool,
+    pub force: bool,
+    pub capabilities: bool,
+    pub json: bool,
+    /// Stay attached to the terminal instead of forking into the background.
+    pub foreground: bool,
+    pub version: bool,
+    /// Print battery status to stdout instead of giving up when there's no tray host.
+    pub status: bool,
+    /// Serials to ignore for this run, on top of `ignore_serials` in the config.
+    pub ignore: Vec<String>,
+    /// Serial of a device whose raw introspection XML to print.
+    pub introspect: Option<String>,
+    /// Lighting profile from the config to apply before exiting.
+    #[cfg(feature = "lighting")]
+    pub profile: Option<String>,
+}
+
+impl Args {
+    pub fn parse() -> Result<Self, String> {
+        let mut args = Self::default();
+        let mut argv = env::args().skip(1);
+        while let Some(arg) = argv.next() {
+            match arg.as_str() {
+                "--install-service" => args.install_service = true,
+                "--force" => args.force = true,
+                "--capabilities" => args.capabilities = true,
+                "--json" => args.json = true,
+                "--foreground" | "-f" => args.foreground = true,
+                "--version" | "-V" => args.version = true,
+                "--status" => args.status = true,
+                "--ignore" => match argv.next() {
+                    Some(serial) => args.ignore.push(serial),
+                    None => return Err("--ignore needs a device serial".to_string()),
+                },
+                "--introspect" => match argv.next() {
+                    Some(serial) => args.introspect = Some(serial),
+                    None => return Err("--introspect needs a device serial".to_string()),
+                },
+                #[cfg(feature = "lighting")]
+                "--profile" => match argv.next() {
+                    Some(name) => args.profile = Some(name),
+                    None => return Err("--profile needs a profile name".to_string()),
+                },
+                other => return Err(format!("Unknown argument: {other}")),
+            }
+        }
+        if args.force && !args.install_service {
+            return Err("--force can only be used with --install-service".to_string());
+        }
+        if args.json && !args.capabilities {
+            return Err("--json can only be used with --capabilities".to_string());
+        }
+        Ok(args)
+    }
+}
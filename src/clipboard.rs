@@ -0,0 +1,65 @@
+use std::env;
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClipboardKind {
+    /// wl-copy on Wayland sessions, xclip otherwise.
+    #[default]
+    Auto,
+    Wayland,
+    X11,
+}
+
+pub trait Clipboard: Send + Sync {
+    fn copy(&self, text: &str) -> io::Result<()>;
+}
+
+/// Pipes the text into an external clipboard tool such as `wl-copy` or `xclip`.
+pub struct CommandClipboard {
+    program: &'static str,
+    args: &'static [&'static str],
+}
+
+impl Clipboard for CommandClipboard {
+    fn copy(&self, text: &str) -> io::Result<()> {
+        let mut child = Command::new(self.program)
+            .args(self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", self.program)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(text.as_bytes())?;
+        }
+        let status = child.wait()?;
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::other(format!("{} exited with {status}", self.program)))
+        }
+    }
+}
+
+pub fn backend(kind: ClipboardKind) -> Box<dyn Clipboard> {
+    let wayland = match kind {
+        ClipboardKind::Auto => env::var_os("WAYLAND_DISPLAY").is_some(),
+        ClipboardKind::Wayland => true,
+        ClipboardKind::X11 => false,
+    };
+    if wayland {
+        Box::new(CommandClipboard {
+            program: "wl-copy",
+            args: &[],
+        })
+    } else {
+        Box::new(CommandClipboard {
+            program: "xclip",
+            args: &["-selection", "clipboard"],
+        })
+    }
+}
@@ -0,0 +1,110 @@
+use std::collections::BTreeMap;
+
+use rusty_razer_tray::openrazer::{Manager, RazerError};
+use serde_json::json;
+
+/// Prints this crate's version and, when the daemon is reachable, OpenRazer's.
+pub async fn print_version() {
+    println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let daemon = match Manager::new().await {
+        Ok(manager) if manager.is_daemon_running().await.unwrap_or(false) => manager
+            .get_daemon_version()
+            .await
+            .unwrap_or_else(|_| "unknown".to_string()),
+        _ => "not running".to_string(),
+    };
+    println!("OpenRazer: {daemon}");
+}
+
+/// Prints the features each device was recognised to have, then every `interface;method` it
+/// advertises, grouped by interface. A device that can't be opened gets an error line and the
+/// rest are still listed, since that device is usually the one a bug report is about.
+pub async fn dump_capabilities(as_json: bool) -> Result<(), RazerError> {
+    let manager = Manager::new().await?;
+    let mut report = Vec::new();
+    for path in manager.get_devices().await? {
+        let path_text = path.to_string();
+        let device = match manager.get_device(path).await {
+            Ok(device) => device,
+            Err(err) => {
+                report.push(Err((path_text, err.to_string())));
+                continue;
+            }
+        };
+        let name = device
+            .get_device_name()
+            .await
+            .unwrap_or_else(|_| "Unknown device".to_string());
+
+        let mut interfaces: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for entry in device.capabilities() {
+            match entry.split_once(';') {
+                Some((interface, method)) => {
+                    interfaces
+                        .entry(interface.to_string())
+                        .or_default()
+                        .push(method.to_string());
+                }
+                None => {
+                    interfaces.entry(entry.clone()).or_default();
+                }
+            }
+        }
+        for methods in interfaces.values_mut() {
+            methods.sort();
+        }
+        report.push(Ok((name, path_text, device.features(), interfaces)));
+    }
+
+    if as_json {
+        let devices: Vec<_> = report
+            .iter()
+            .map(|entry| match entry {
+                Ok((name, path, features, interfaces)) => json!({
+                    "name": name,
+                    "object_path": path,
+                    "features": features,
+                    "interfaces": interfaces,
+                }),
+                Err((path, error)) => json!({ "object_path": path, "error": error }),
+            })
+            .collect();
+        let output = serde_json::to_string_pretty(&devices)
+            .map_err(|err| RazerError::Parse(err.to_string()))?;
+        println!("{output}");
+        return Ok(());
+    }
+
+    if report.is_empty() {
+        println!("No OpenRazer devices found");
+    }
+    for entry in report {
+        let (name, path, features, interfaces) = match entry {
+            Ok(device) => device,
+            Err((path, error)) => {
+                println!("{path}: failed to read capabilities: {error}");
+                continue;
+            }
+        };
+        println!("{name} ({path})");
+        if !features.is_empty() {
+            println!("  features: {}", features.join(", "));
+        }
+        for (interface, methods) in interfaces {
+            println!("  {interface}");
+            for method in methods {
+                println!("    {method}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Prints the unmodified Introspect XML of the device with `serial`, which is what capability
+/// detection works from.
+pub async fn print_introspection(serial: &str) -> Result<(), RazerError> {
+    let manager = Manager::new().await?;
+    let device = manager.get_device_by_serial(serial).await?;
+    print!("{}", device.raw_introspection().await?);
+    Ok(())
+}
@@ -0,0 +1,389 @@
+#[cfg(feature = "lighting")]
+use std::collections::BTreeMap;
+#[cfg(feature = "dpi")]
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+use rusty_razer_tray::openrazer::DeviceOrder;
+#[cfg(feature = "lighting")]
+use rusty_razer_tray::openrazer::Rgb;
+#[cfg(feature = "lighting")]
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer};
+
+use crate::clipboard::ClipboardKind;
+use crate::notifications::NotifierKind;
+
+/// ARGB colour as consumed by the tray pixmap.
+pub type Colour = (u8, u8, u8, u8);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub icon: IconTheme,
+    /// Which tool "Copy serial" hands the text to.
+    pub clipboard: ClipboardKind,
+    /// Where notifications go: `backend = "dbus"`, `"notify-send"`, or `"fifo"` with a `path`.
+    pub notifications: NotifierKind,
+    #[cfg(feature = "lighting")]
+    pub lighting: LightingConfig,
+    /// DPI stages to apply when a device appears, keyed by serial.
+    #[cfg(feature = "dpi")]
+    pub dpi: HashMap<String, DpiStagesConfig>,
+    pub battery: BatteryConfig,
+    pub dbus: DbusConfig,
+    /// Custom tray title, e.g. `"{name} {percent}%{charging}"`. Unset keeps the built-in one.
+    pub title_format: Option<String>,
+    /// Which device the icon follows when several report a battery: `"daemon"`, `"type"` (mice
+    /// first) or `"name"`.
+    pub device_order: DeviceOrder,
+    /// Serials left out of the tray entirely, e.g. a dock that misreports its battery.
+    pub ignore_serials: HashSet<String>,
+    #[cfg(feature = "temperature")]
+    pub temperature: TemperatureConfig,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct DbusConfig {
+    /// How long to wait on any single call before treating the daemon as wedged.
+    pub timeout_ms: u64,
+}
+
+impl Default for DbusConfig {
+    fn default() -> Self {
+        Self { timeout_ms: 5000 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct BatteryConfig {
+    /// Averages the displayed percentage over roughly this many polls to hide the jumpy steps
+    /// some wireless mice report. Larger windows are steadier but slower to show real changes;
+    /// 0 shows raw readings.
+    pub smoothing_window: u8,
+    /// How long a battery reading is reused when several parts of the tray ask for it at once.
+    pub cache_ttl_ms: u64,
+    /// Hide the tray icon while the device is charging or has no battery to report, e.g. for a
+    /// mouse that is mostly used wired.
+    pub only_on_battery: bool,
+    /// Poll slower while every battery holds steady, backing off from `min_poll_ms` up to
+    /// `max_poll_ms`, and at the fastest rate while charging or close to the low threshold.
+    pub adaptive_polling: bool,
+    pub min_poll_ms: u64,
+    pub max_poll_ms: u64,
+}
+
+impl Default for BatteryConfig {
+    fn default() -> Self {
+        Self {
+            smoothing_window: 0,
+            cache_ttl_ms: 500,
+            only_on_battery: false,
+            adaptive_polling: false,
+            min_poll_ms: 1000,
+            max_poll_ms: 60_000,
+        }
+    }
+}
+
+#[cfg(feature = "dpi")]
+#[derive(Debug, Clone, Deserialize)]
+pub struct DpiStagesConfig {
+    /// One DPI value per stage, used for both axes.
+    pub stages: Vec<u16>,
+    /// 1-based, matching how OpenRazer and the mouse's stage indicator count.
+    #[serde(default = "default_active_stage")]
+    pub active: u8,
+}
+
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct LightingConfig {
+    /// White used whenever the tray sets a static white, as `"warm"`, `"cool"` or `"#RRGGBB"`.
+    #[serde(deserialize_with = "deserialize_white")]
+    pub white: Rgb,
+    /// Remember effects set from the tray and put them back when the device next appears.
+    pub persist_effects: bool,
+    /// Serials whose lighting is switched off once their battery runs low.
+    pub off_on_low_battery: HashSet<String>,
+    /// Named profiles for `--profile NAME` and the tray's "Lighting profile" menu.
+    pub profiles: BTreeMap<String, LightingProfile>,
+}
+
+/// Effects by LED name (`logo`, `scroll`, `backlight`, ...), matched without regard to case so
+/// one profile can cover several kinds of device, e.g.
+/// `logo = { effect = "static", color = "#00ff00" }`.
+#[cfg(feature = "lighting")]
+pub type LightingProfile = BTreeMap<String, ProfileEffect>;
+
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(tag = "effect", rename_all = "lowercase")]
+pub enum ProfileEffect {
+    /// `color` takes the same `"warm"`, `"cool"` or `"#RRGGBB"` forms as `white`.
+    Static {
+        #[serde(deserialize_with = "deserialize_white")]
+        color: Rgb,
+    },
+    None,
+}
+
+#[cfg(feature = "lighting")]
+impl Default for LightingConfig {
+    fn default() -> Self {
+        Self {
+            white: Rgb::warm_white(),
+            persist_effects: false,
+            off_on_low_battery: HashSet::new(),
+            profiles: BTreeMap::new(),
+        }
+    }
+}
+
+/// Colours devices by CPU temperature, from green at `cool` to red at `hot` (both in °C).
+#[cfg(feature = "temperature")]
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct TemperatureConfig {
+    /// Serials whose lighting follows the temperature. Empty leaves the feature off.
+    pub serials: HashSet<String>,
+    pub cool: f64,
+    pub hot: f64,
+    /// The hwmon `name` to read, e.g. `"k10temp"`. Unset picks the first known CPU sensor.
+    pub sensor: Option<String>,
+}
+
+#[cfg(feature = "temperature")]
+impl Default for TemperatureConfig {
+    fn default() -> Self {
+        Self {
+            serials: HashSet::new(),
+            cool: 40.0,
+            hot: 90.0,
+            sensor: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    /// Nub on the right, filling left to right.
+    #[default]
+    Horizontal,
+    /// Nub on top, filling from the bottom up like a phone battery.
+    Vertical,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconStyle {
+    /// The coloured battery pixmap drawn by the tray, which suits KDE.
+    #[default]
+    Color,
+    /// Named `battery-level-*-symbolic` icons from the icon theme, which GNOME tints to match
+    /// its top bar. The percentage is still in the title and tooltip.
+    Symbolic,
+}
+
+/// Where the battery fill colour comes from.
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IconColor {
+    /// The `low`/`medium`/`high` colours, by charge level.
+    #[default]
+    Battery,
+    /// The device's static lighting colour, falling back to `Battery` when it has none.
+    Device,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(default)]
+pub struct IconTheme {
+    pub style: IconStyle,
+    pub orientation: Orientation,
+    #[cfg(feature = "lighting")]
+    pub icon_color: IconColor,
+    /// Show the device's product picture, downloaded once and cached, in place of the type glyph.
+    #[cfg(feature = "reqwest")]
+    pub device_image: bool,
+    /// Percentages at or below this use the `low` colour.
+    pub low_threshold: u8,
+    /// Percentages at or below this (and above `low_threshold`) use the `medium` colour.
+    pub medium_threshold: u8,
+    #[serde(deserialize_with = "deserialize_rgba")]
+    pub outline: Colour,
+    #[serde(deserialize_with = "deserialize_rgba")]
+    pub low: Colour,
+    #[serde(deserialize_with = "deserialize_rgba")]
+    pub medium: Colour,
+    #[serde(deserialize_with = "deserialize_rgba")]
+    pub high: Colour,
+}
+
+impl Default for IconTheme {
+    fn default() -> Self {
+        Self {
+            style: IconStyle::Color,
+            orientation: Orientation::Horizontal,
+            #[cfg(feature = "lighting")]
+            icon_color: IconColor::Battery,
+            #[cfg(feature = "reqwest")]
+            device_image: false,
+            low_threshold: 25,
+            medium_threshold: 50,
+            outline: (255, 220, 220, 220),
+            low: (255, 220, 60, 60),
+            medium: (255, 255, 224, 0),
+            high: (255, 0, 255, 0),
+        }
+    }
+}
+
+impl IconTheme {
+    pub fn fill_for(&self, value: u8) -> Colour {
+        if value <= self.low_threshold {
+            self.low
+        } else if value <= self.medium_threshold {
+            self.medium
+        } else {
+            self.high
+        }
+    }
+}
+
+impl Config {
+    /// Loads `$XDG_CONFIG_HOME/rusty-razer-tray/config.toml`, falling back to defaults when the
+    /// file is missing or invalid.
+    pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|err| {
+            log::warn!("{err}");
+            Self::default()
+        })
+    }
+
+    /// Like `load`, but an unreadable or malformed file is an error rather than the defaults,
+    /// so a reload can keep the last good config. A missing file still means the defaults.
+    pub fn try_load() -> Result<Self, String> {
+        let Some(path) = config_path() else {
+            return Ok(Self::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => toml::from_str(&contents)
+                .map_err(|err| format!("Failed to parse {}: {err}", path.display())),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(err) => Err(format!("Failed to read {}: {err}", path.display())),
+        }
+    }
+}
+
+/// `$var` when it is set and non-empty, otherwise `fallback` under `$HOME`, following the XDG
+/// base directory spec.
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => Some(PathBuf::from(env::var_os("HOME")?).join(fallback)),
+    }
+}
+
+pub fn xdg_config_home() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_config_home().map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+#[cfg(feature = "lighting")]
+pub fn state_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_STATE_HOME", ".local/state").map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+#[cfg(feature = "reqwest")]
+pub fn cache_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache").map(|dir| dir.join(env!("CARGO_PKG_NAME")))
+}
+
+pub fn config_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
+
+#[cfg(feature = "dpi")]
+fn default_active_stage() -> u8 {
+    1
+}
+
+/// Colours are written as `[r, g, b, a]` in the config file.
+fn deserialize_rgba<'de, D>(deserializer: D) -> Result<Colour, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let [r, g, b, a] = <[u8; 4]>::deserialize(deserializer)?;
+    Ok((a, r, g, b))
+}
+
+#[cfg(feature = "lighting")]
+fn deserialize_white<'de, D>(deserializer: D) -> Result<Rgb, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    match value.as_str() {
+        "warm" => Ok(Rgb::warm_white()),
+        "cool" => Ok(Rgb::cool_white()),
+        hex => Rgb::from_hex(hex).map_err(D::Error::custom),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_file_gives_the_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        let defaults = IconTheme::default();
+        assert_eq!(config.icon.low_threshold, defaults.low_threshold);
+        assert_eq!(config.icon.medium_threshold, defaults.medium_threshold);
+        assert_eq!(config.icon.high, defaults.high);
+        assert_eq!(config.icon.style, IconStyle::Color);
+    }
+
+    #[test]
+    fn icon_keys_override_only_themselves() {
+        let config: Config = toml::from_str(
+            "[icon]\n\
+             low_threshold = 10\n\
+             high = [0, 128, 255, 200]\n",
+        )
+        .unwrap();
+        assert_eq!(config.icon.low_threshold, 10);
+        assert_eq!(config.icon.medium_threshold, 50);
+        // Written as RGBA, kept as ARGB for the pixmap.
+        assert_eq!(config.icon.high, (200, 0, 128, 255));
+        assert_eq!(config.icon.low, IconTheme::default().low);
+    }
+
+    #[test]
+    fn malformed_colours_are_rejected() {
+        assert!(toml::from_str::<Config>("[icon]\nlow = [255, 0, 0]\n").is_err());
+        assert!(toml::from_str::<Config>("[icon]\nlow = [256, 0, 0, 0]\n").is_err());
+    }
+
+    #[test]
+    fn fill_colour_follows_the_thresholds() {
+        let theme = IconTheme::default();
+        assert_eq!(theme.fill_for(25), theme.low);
+        assert_eq!(theme.fill_for(26), theme.medium);
+        assert_eq!(theme.fill_for(50), theme.medium);
+        assert_eq!(theme.fill_for(51), theme.high);
+    }
+}
@@ -0,0 +1,73 @@
+#[cfg(feature = "lighting")]
+use std::sync::Arc;
+use std::time::Duration;
+
+use log::{debug, info, warn};
+use notify::{RecursiveMode, Watcher};
+use tokio::sync::{mpsc, watch};
+
+use crate::config::{self, Config};
+use crate::TrayHandle;
+
+/// Editors often save in several steps (truncate, write, rename), so a reload waits until the
+/// file has been quiet for this long.
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+/// Re-reads the config file whenever it changes and applies what the running tray can pick up
+/// without a restart. The menu's theme, white and profiles are updated here, and the config is
+/// sent on `updates` for the poll loop's battery, polling and lighting settings. D-Bus settings
+/// and the device order still need a restart. Malformed edits are logged and the last good
+/// config stays in effect.
+pub async fn watch(handle: TrayHandle, updates: watch::Sender<Config>) {
+    let Some(path) = config::config_path() else {
+        return;
+    };
+    let Some(dir) = path.parent() else {
+        return;
+    };
+    let (events, mut changes) = mpsc::unbounded_channel();
+    let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if let Ok(event) = event {
+            let _ = events.send(event);
+        }
+    });
+    let mut watcher = match watcher {
+        Ok(watcher) => watcher,
+        Err(err) => {
+            warn!("Failed to start watching the config file: {err}");
+            return;
+        }
+    };
+    // Watch the directory, since saving by rename replaces the file and would end a file watch.
+    if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+        debug!("Not watching {} for config changes: {err}", dir.display());
+        return;
+    }
+
+    while let Some(event) = changes.recv().await {
+        // Reading the file below raises access events of its own, which must not loop back.
+        if event.kind.is_access() || !event.paths.contains(&path) {
+            continue;
+        }
+        while let Ok(Some(_)) = tokio::time::timeout(DEBOUNCE, changes.recv()).await {}
+        let config = match Config::try_load() {
+            Ok(config) => config,
+            Err(err) => {
+                warn!("{err}; keeping the previous config");
+                continue;
+            }
+        };
+        info!("Reloaded {}", path.display());
+        updates.send_replace(config.clone());
+        handle
+            .update(move |tray| {
+                tray.theme = config.icon;
+                #[cfg(feature = "lighting")]
+                {
+                    tray.white = config.lighting.white;
+                    tray.profiles = Arc::new(config.lighting.profiles);
+                }
+            })
+            .await;
+    }
+}
@@ -0,0 +1,85 @@
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use image::imageops::FilterType;
+use log::{debug, info};
+use rusty_razer_tray::openrazer::Device;
+
+use crate::config;
+use crate::icons;
+
+/// Matches the size of the generated icons, so the picture can stand in for the glyph.
+const ICON_SIZE: u32 = icons::DEFAULT_SIZE;
+const DOWNLOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The device's product picture from `getRazerUrls`, scaled down to a tray icon. The original
+/// is cached under `$XDG_CACHE_HOME`, so only the first call for a device needs the network.
+/// Any failure (no URL, offline, undecodable image) gives `None` and the glyph is used instead.
+pub async fn tray_icon(device: &Device, serial: &str) -> Option<ksni::Icon> {
+    let bytes = match cached_or_download(device, serial).await {
+        Ok(bytes) => bytes,
+        Err(err) => {
+            debug!("No device image for {serial}: {err}");
+            return None;
+        }
+    };
+    match image::load_from_memory(&bytes) {
+        Ok(image) => Some(to_icon(image)),
+        Err(err) => {
+            debug!("Failed to decode the device image for {serial}: {err}");
+            None
+        }
+    }
+}
+
+async fn cached_or_download(device: &Device, serial: &str) -> io::Result<Vec<u8>> {
+    let path = cache_path(serial)?;
+    match fs::read(&path) {
+        Ok(bytes) => return Ok(bytes),
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        Err(_) => {}
+    }
+    let url = device.get_device_image_url().await.map_err(io::Error::other)?;
+    if url.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::NotFound, "the daemon has no image URL"));
+    }
+    let bytes = download(&url).await.map_err(io::Error::other)?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(&path, &bytes)?;
+    info!("Cached the device image for {serial} at {}", path.display());
+    Ok(bytes)
+}
+
+async fn download(url: &str) -> reqwest::Result<Vec<u8>> {
+    let client = reqwest::Client::builder().timeout(DOWNLOAD_TIMEOUT).build()?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    Ok(response.bytes().await?.to_vec())
+}
+
+fn cache_path(serial: &str) -> io::Result<PathBuf> {
+    let dir = config::cache_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?;
+    Ok(dir.join("images").join(serial))
+}
+
+/// Fits the image into the icon square, centred, keeping its aspect ratio.
+fn to_icon(image: image::DynamicImage) -> ksni::Icon {
+    let scaled = image.resize(ICON_SIZE, ICON_SIZE, FilterType::Lanczos3).to_rgba8();
+    let offset_x = (ICON_SIZE - scaled.width()) / 2;
+    let offset_y = (ICON_SIZE - scaled.height()) / 2;
+    let mut data = vec![0u8; (ICON_SIZE * ICON_SIZE * 4) as usize];
+    for (x, y, pixel) in scaled.enumerate_pixels() {
+        let [r, g, b, a] = pixel.0;
+        let idx = (((y + offset_y) * ICON_SIZE + x + offset_x) * 4) as usize;
+        data[idx..idx + 4].copy_from_slice(&[a, r, g, b]);
+    }
+    ksni::Icon {
+        width: ICON_SIZE as i32,
+        height: ICON_SIZE as i32,
+        data,
+    }
+}
@@ -0,0 +1,149 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use log::{info, warn};
+use rusty_razer_tray::openrazer::{Device, Dpi};
+
+use crate::config::DpiStagesConfig;
+
+/// Applies configured DPI stages to devices that appeared since the last call. `applied` holds
+/// the serials already configured and still connected, so each device is set up once per plug-in.
+pub async fn apply_configured_stages(
+    devices: &[Arc<Device>],
+    configured: &HashMap<String, DpiStagesConfig>,
+    applied: &mut HashSet<String>,
+) {
+    if configured.is_empty() {
+        return;
+    }
+    let present: HashMap<&str, &Device> = devices
+        .iter()
+        .filter_map(|device| Some((device.serial_from_path()?, device.as_ref())))
+        .collect();
+    applied.retain(|serial| present.contains_key(serial.as_str()));
+
+    for (serial, config) in configured {
+        let Some(device) = present.get(serial.as_str()) else {
+            continue;
+        };
+        if !applied.insert(serial.clone()) {
+            continue;
+        }
+        match apply_stages(device, config).await {
+            Ok(()) => info!("Applied {} DPI stages to {serial}", config.stages.len()),
+            Err(err) => warn!("Not applying DPI stages to {serial}: {err}"),
+        }
+    }
+}
+
+async fn apply_stages(device: &Device, config: &DpiStagesConfig) -> Result<(), String> {
+    if !device.has_feature("dpi_stages") {
+        return Err("device does not support DPI stages".to_string());
+    }
+    let max_dpi = device.max_dpi().await.map_err(|err| err.to_string())?;
+    let max_stages = device.max_dpi_stages().await.map_err(|err| err.to_string())?;
+    let allowed = if device.has_feature("restricted_dpi") {
+        Some(device.get_allowed_dpi().await.map_err(|err| err.to_string())?)
+    } else {
+        None
+    };
+    let stages = validate_stages(config, max_stages, max_dpi, allowed.as_deref())?;
+    device
+        .set_dpi_stages(config.active, stages)
+        .await
+        .map_err(|err| err.to_string())
+}
+
+fn validate_stages(
+    config: &DpiStagesConfig,
+    max_stages: u8,
+    max_dpi: u16,
+    allowed: Option<&[u16]>,
+) -> Result<Vec<Dpi>, String> {
+    let count = config.stages.len();
+    if !(1..=usize::from(max_stages)).contains(&count) {
+        return Err(format!("expected 1 to {max_stages} stages but found {count}"));
+    }
+    if config.active == 0 || usize::from(config.active) > count {
+        return Err(format!("active stage {} is not between 1 and {count}", config.active));
+    }
+    config
+        .stages
+        .iter()
+        .map(|&value| {
+            let dpi = Dpi::uniform(value);
+            if !dpi.is_valid_for(max_dpi) {
+                return Err(format!("{value} DPI is outside 1..={max_dpi}"));
+            }
+            if allowed.is_some_and(|allowed| !allowed.contains(&value)) {
+                return Err(format!("{value} DPI is not one of the device's allowed values"));
+            }
+            Ok(dpi)
+        })
+        .collect()
+}
+
+/// The tooltip line for a mouse's current DPI, e.g. "DPI: 1600" or "DPI: 1600x1800".
+pub fn tooltip_line(dpi: &Dpi) -> String {
+    if dpi.dpi_y == 0 || dpi.dpi_y == dpi.dpi_x {
+        format!("DPI: {}", dpi.dpi_x)
+    } else {
+        format!("DPI: {}x{}", dpi.dpi_x, dpi.dpi_y)
+    }
+}
+
+pub fn stage_label(dpi: &Dpi) -> String {
+    if dpi.dpi_y == 0 || dpi.dpi_y == dpi.dpi_x {
+        format!("{} DPI", dpi.dpi_x)
+    } else {
+        format!("{}x{} DPI", dpi.dpi_x, dpi.dpi_y)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(stages: &[u16], active: u8) -> DpiStagesConfig {
+        DpiStagesConfig {
+            stages: stages.to_vec(),
+            active,
+        }
+    }
+
+    #[test]
+    fn accepts_stages_within_the_device_limits() {
+        let stages = validate_stages(&config(&[800, 1600], 2), 5, 16000, None).unwrap();
+        assert_eq!(stages, [Dpi::uniform(800), Dpi::uniform(1600)]);
+    }
+
+    #[test]
+    fn stage_count_is_checked_against_the_device() {
+        let three = config(&[400, 800, 1600], 1);
+        assert!(validate_stages(&three, 3, 16000, None).is_ok());
+        let err = validate_stages(&three, 2, 16000, None).unwrap_err();
+        assert_eq!(err, "expected 1 to 2 stages but found 3");
+        assert!(validate_stages(&config(&[], 1), 5, 16000, None).is_err());
+    }
+
+    #[test]
+    fn active_stage_must_be_one_of_the_stages() {
+        assert!(validate_stages(&config(&[800, 1600], 0), 5, 16000, None).is_err());
+        assert!(validate_stages(&config(&[800, 1600], 3), 5, 16000, None).is_err());
+    }
+
+    #[test]
+    fn values_must_suit_the_sensor() {
+        assert!(validate_stages(&config(&[20000], 1), 5, 16000, None).is_err());
+        let allowed = [400, 800, 1600];
+        assert!(validate_stages(&config(&[800], 1), 5, 16000, Some(&allowed)).is_ok());
+        assert!(validate_stages(&config(&[1000], 1), 5, 16000, Some(&allowed)).is_err());
+    }
+
+    #[test]
+    fn stage_labels_show_both_axes_only_when_they_differ() {
+        assert_eq!(stage_label(&Dpi::uniform(1600)), "1600 DPI");
+        assert_eq!(stage_label(&Dpi { dpi_x: 1600, dpi_y: 0 }), "1600 DPI");
+        assert_eq!(stage_label(&Dpi { dpi_x: 1600, dpi_y: 800 }), "1600x800 DPI");
+    }
+}
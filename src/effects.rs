@@ -0,0 +1,122 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use log::{debug, info, warn};
+use rusty_razer_tray::openrazer::{Device, RazerError, Rgb};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+/// The tray's record of what it last applied, shared between the menu and the poll loop.
+pub type SharedEffectStore = Arc<Mutex<EffectStore>>;
+
+/// An effect the tray can put back on an LED. Only effects the tray itself sets are listed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "lowercase")]
+pub enum SavedEffect {
+    Static { color: Rgb },
+    None,
+}
+
+/// Last-applied effect per device serial and LED name, kept in a state file because OpenRazer
+/// doesn't always restore effects after a reboot or replug.
+#[derive(Debug, Default)]
+pub struct EffectStore {
+    path: Option<PathBuf>,
+    devices: BTreeMap<String, BTreeMap<String, SavedEffect>>,
+}
+
+impl EffectStore {
+    pub fn load() -> Self {
+        let path = config::state_dir().map(|dir| dir.join("effects.json"));
+        let devices = match path.as_ref().map(fs::read_to_string) {
+            Some(Ok(contents)) => serde_json::from_str(&contents).unwrap_or_else(|err| {
+                warn!("Ignoring unreadable saved effects: {err}");
+                BTreeMap::new()
+            }),
+            Some(Err(err)) if err.kind() != io::ErrorKind::NotFound => {
+                warn!("Failed to read saved effects: {err}");
+                BTreeMap::new()
+            }
+            _ => BTreeMap::new(),
+        };
+        Self { path, devices }
+    }
+
+    pub fn record(&mut self, serial: &str, led: &str, effect: SavedEffect) {
+        self.devices
+            .entry(serial.to_string())
+            .or_default()
+            .insert(led.to_string(), effect);
+        if let Err(err) = self.save() {
+            warn!("Failed to save effects: {err}");
+        }
+    }
+
+    fn saved(&self, serial: &str) -> Option<BTreeMap<String, SavedEffect>> {
+        self.devices.get(serial).cloned()
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let contents = serde_json::to_string_pretty(&self.devices).map_err(io::Error::other)?;
+        fs::write(path, contents)
+    }
+}
+
+/// Re-applies saved effects to devices that appeared since the last call. `replayed` holds the
+/// serials already handled and still connected, so each device is restored once per plug-in.
+pub async fn replay_saved_effects(
+    devices: &[Arc<Device>],
+    store: &SharedEffectStore,
+    replayed: &mut HashSet<String>,
+) {
+    let present: HashMap<&str, &Device> = devices
+        .iter()
+        .filter_map(|device| Some((device.serial_from_path()?, device.as_ref())))
+        .collect();
+    replayed.retain(|serial| present.contains_key(serial.as_str()));
+
+    for (serial, device) in present {
+        if !replayed.insert(serial.to_string()) {
+            continue;
+        }
+        let Some(saved) = store.lock().unwrap().saved(serial) else {
+            continue;
+        };
+        replay(device, serial, saved).await;
+    }
+}
+
+async fn replay(device: &Device, serial: &str, saved: BTreeMap<String, SavedEffect>) {
+    for (name, effect) in saved {
+        // LEDs are matched by name, so a firmware update that drops one just skips it.
+        let Some(led) = device
+            .supported_leds()
+            .iter()
+            .find_map(|(led, led_name)| (*led_name == name).then_some(*led))
+        else {
+            debug!("{serial} no longer has a {name} LED; not restoring its effect");
+            continue;
+        };
+        let result = match effect {
+            SavedEffect::Static { color } => device.set_static(led, color).await,
+            SavedEffect::None => device.set_none(led).await,
+        };
+        match result {
+            Ok(()) => info!("Restored {name} effect on {serial}"),
+            Err(RazerError::Unsupported { .. }) => {
+                debug!("{serial} no longer supports the saved {name} effect; skipping it")
+            }
+            Err(err) => warn!("Failed to restore {name} effect on {serial}: {err}"),
+        }
+    }
+}
@@ -0,0 +1,96 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
+
+/// How many distinct percentage readings to extrapolate from.
+const MAX_SAMPLES: usize = 8;
+/// Anything outside this range is treated as noise rather than shown to the user.
+const MIN_ETA: Duration = Duration::from_secs(60);
+const MAX_ETA: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Estimates time to full charge from the rate the percentage has been climbing, per device.
+#[derive(Default)]
+pub struct ChargeEstimator {
+    samples: HashMap<String, VecDeque<(Instant, u8)>>,
+}
+
+impl ChargeEstimator {
+    pub fn record(
+        &mut self,
+        device: &str,
+        percent: u8,
+        charging: bool,
+        now: Instant,
+    ) -> Option<Duration> {
+        if !charging {
+            self.samples.remove(device);
+            return None;
+        }
+        let samples = self.samples.entry(device.to_string()).or_default();
+        // Only changes are recorded; a reading repeats for many polls between steps.
+        if samples.back().is_none_or(|&(_, last)| last != percent) {
+            if samples.len() == MAX_SAMPLES {
+                samples.pop_front();
+            }
+            samples.push_back((now, percent));
+        }
+
+        let (&(first_at, first), &(last_at, last)) = (samples.front()?, samples.back()?);
+        if last <= first {
+            return None;
+        }
+        let per_percent = (last_at - first_at) / u32::from(last - first);
+        let eta = per_percent * u32::from(100 - percent.min(100));
+        (MIN_ETA..=MAX_ETA).contains(&eta).then_some(eta)
+    }
+}
+
+pub fn format_eta(eta: Duration) -> String {
+    let minutes = eta.as_secs().div_ceil(60);
+    if minutes < 60 {
+        format!("~{minutes} min to full")
+    } else {
+        format!("~{} h {} min to full", minutes / 60, minutes % 60)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: Duration = Duration::from_secs(60);
+
+    #[test]
+    fn extrapolates_from_the_charge_rate() {
+        let mut estimator = ChargeEstimator::default();
+        let start = Instant::now();
+        assert_eq!(estimator.record("mouse", 50, true, start), None);
+        // Repeated readings don't count as samples, so the rate stays one point a minute.
+        assert_eq!(estimator.record("mouse", 50, true, start + MINUTE / 2), None);
+        let eta = estimator.record("mouse", 51, true, start + MINUTE);
+        assert_eq!(eta, Some(MINUTE * 49));
+    }
+
+    #[test]
+    fn unplugging_forgets_the_samples() {
+        let mut estimator = ChargeEstimator::default();
+        let start = Instant::now();
+        estimator.record("mouse", 50, true, start);
+        assert_eq!(estimator.record("mouse", 51, false, start + MINUTE), None);
+        assert_eq!(estimator.record("mouse", 52, true, start + MINUTE * 2), None);
+    }
+
+    #[test]
+    fn implausible_estimates_are_dropped() {
+        let mut estimator = ChargeEstimator::default();
+        let start = Instant::now();
+        estimator.record("mouse", 50, true, start);
+        assert_eq!(estimator.record("mouse", 99, true, start + Duration::from_secs(1)), None);
+        assert_eq!(estimator.record("mouse", 48, true, start + MINUTE), None);
+    }
+
+    #[test]
+    fn formats_minutes_and_hours() {
+        assert_eq!(format_eta(MINUTE * 49), "~49 min to full");
+        assert_eq!(format_eta(MINUTE * 125 - Duration::from_secs(30)), "~2 h 5 min to full");
+    }
+}
@@ -0,0 +1,202 @@
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use eframe::egui;
+use log::warn;
+use rusty_razer_tray::openrazer::{Device, Dpi, LedId, RazerError, Rgb};
+use tokio::runtime::Handle;
+use winit::platform::{wayland::EventLoopBuilderExtWayland, x11::EventLoopBuilderExtX11};
+
+/// winit allows one event loop per process, so the window is created once and then only hidden
+/// and shown again.
+static WINDOW: Mutex<Option<Window>> = Mutex::new(None);
+
+struct Window {
+    devices: Sender<Arc<Device>>,
+    context: Arc<OnceLock<egui::Context>>,
+}
+
+/// Opens the settings window for `device`, or brings the existing one back showing it. Device
+/// calls are spawned on `runtime`, so the window never blocks on D-Bus.
+pub fn open(device: Arc<Device>, runtime: Handle) {
+    let mut window = WINDOW.lock().unwrap();
+    if let Some(window) = window.as_ref() {
+        let _ = window.devices.send(device);
+        if let Some(context) = window.context.get() {
+            context.send_viewport_cmd(egui::ViewportCommand::Visible(true));
+            context.send_viewport_cmd(egui::ViewportCommand::Focus);
+        }
+        return;
+    }
+    let (devices, incoming) = mpsc::channel();
+    let _ = devices.send(device);
+    let context = Arc::new(OnceLock::new());
+    *window = Some(Window { devices, context: context.clone() });
+    let spawned = thread::Builder::new()
+        .name("settings".to_string())
+        .spawn(move || {
+            if let Err(err) = run(incoming, context, runtime) {
+                warn!("Settings window failed: {err}");
+                WINDOW.lock().unwrap().take();
+            }
+        });
+    if let Err(err) = spawned {
+        warn!("Failed to start the settings window: {err}");
+        window.take();
+    }
+}
+
+fn run(
+    devices: Receiver<Arc<Device>>,
+    context: Arc<OnceLock<egui::Context>>,
+    runtime: Handle,
+) -> eframe::Result {
+    let options = eframe::NativeOptions {
+        viewport: egui::ViewportBuilder::default().with_inner_size([360.0, 320.0]),
+        // The tray owns the main thread's runtime, so the window lives on its own thread.
+        event_loop_builder: Some(Box::new(|builder| {
+            EventLoopBuilderExtX11::with_any_thread(builder, true);
+            EventLoopBuilderExtWayland::with_any_thread(builder, true);
+        })),
+        ..Default::default()
+    };
+    eframe::run_native(
+        "Razer settings",
+        options,
+        Box::new(move |creation| {
+            let _ = context.set(creation.egui_ctx.clone());
+            Ok(Box::new(Settings::new(devices, runtime)))
+        }),
+    )
+}
+
+/// What the window shows for one device, read in the background when the device is selected.
+#[derive(Default)]
+struct Loaded {
+    name: String,
+    /// Current and maximum DPI, if the device has adjustable DPI.
+    dpi: Option<(u16, u16)>,
+    brightness: Vec<(LedId, String, f64)>,
+}
+
+struct Settings {
+    runtime: Handle,
+    devices: Receiver<Arc<Device>>,
+    device: Option<Arc<Device>>,
+    loaded_tx: Sender<(String, Loaded)>,
+    loaded_rx: Receiver<(String, Loaded)>,
+    shown: Loaded,
+    color: [u8; 3],
+}
+
+impl Settings {
+    fn new(devices: Receiver<Arc<Device>>, runtime: Handle) -> Self {
+        let (loaded_tx, loaded_rx) = mpsc::channel();
+        Self {
+            runtime,
+            devices,
+            device: None,
+            loaded_tx,
+            loaded_rx,
+            shown: Loaded::default(),
+            color: [255, 255, 255],
+        }
+    }
+
+    fn select(&mut self, device: Arc<Device>, context: &egui::Context) {
+        self.shown = Loaded::default();
+        self.device = Some(device.clone());
+        let loaded = self.loaded_tx.clone();
+        let context = context.clone();
+        self.runtime.spawn(async move {
+            let name = device.get_device_name().await.unwrap_or_default();
+            let dpi = match (device.get_dpi().await, device.max_dpi().await) {
+                (Ok(dpi), Ok(max)) => Some((dpi.dpi_x, max)),
+                _ => None,
+            };
+            let mut brightness = Vec::new();
+            for (led, led_name) in device.supported_leds() {
+                if !device.supports_brightness(*led) {
+                    continue;
+                }
+                if let Ok(value) = device.get_brightness(*led).await {
+                    brightness.push((*led, led_name.clone(), value));
+                }
+            }
+            brightness.sort_by(|a, b| a.1.cmp(&b.1));
+            let path = device.object_path().to_string();
+            let _ = loaded.send((path, Loaded { name, dpi, brightness }));
+            context.request_repaint();
+        });
+    }
+}
+
+/// Runs `call` against the selected device in the background, logging failures.
+fn apply<F, Fut>(runtime: &Handle, device: Option<&Arc<Device>>, what: &'static str, call: F)
+where
+    F: FnOnce(Arc<Device>) -> Fut + Send + 'static,
+    Fut: Future<Output = Result<(), RazerError>> + Send,
+{
+    let Some(device) = device.cloned() else {
+        return;
+    };
+    runtime.spawn(async move {
+        if let Err(err) = call(device).await {
+            warn!("Failed to set {what}: {err}");
+        }
+    });
+}
+
+impl eframe::App for Settings {
+    fn update(&mut self, context: &egui::Context, _frame: &mut eframe::Frame) {
+        while let Ok(device) = self.devices.try_recv() {
+            self.select(device, context);
+        }
+        while let Ok((path, loaded)) = self.loaded_rx.try_recv() {
+            // Drop readings for a device that was swapped out while they were in flight.
+            if self.device.as_ref().is_some_and(|device| device.object_path().as_str() == path) {
+                self.shown = loaded;
+            }
+        }
+        if context.input(|input| input.viewport().close_requested()) {
+            context.send_viewport_cmd(egui::ViewportCommand::CancelClose);
+            context.send_viewport_cmd(egui::ViewportCommand::Visible(false));
+        }
+
+        let Self { runtime, device, shown, color, .. } = self;
+        let device = device.as_ref();
+        egui::CentralPanel::default().show(context, |ui| {
+            ui.heading(&shown.name);
+            ui.separator();
+            // Changes are sent once a slider is let go of, not on every step of a drag.
+            if let Some((dpi, max)) = &mut shown.dpi {
+                let response = ui.add(egui::Slider::new(dpi, 100..=*max).text("DPI"));
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    let dpi = Dpi::uniform(*dpi);
+                    apply(runtime, device, "DPI", move |device| async move {
+                        device.set_dpi(dpi).await
+                    });
+                }
+            }
+            for (led, name, value) in &mut shown.brightness {
+                let response = ui.add(egui::Slider::new(value, 0.0..=100.0).text(name.as_str()));
+                if response.drag_stopped() || (response.changed() && !response.dragged()) {
+                    let (led, value) = (*led, *value);
+                    apply(runtime, device, "brightness", move |device| async move {
+                        device.set_brightness(led, value).await
+                    });
+                }
+            }
+            ui.horizontal(|ui| {
+                ui.color_edit_button_srgb(color);
+                if ui.button("Set static colour").clicked() {
+                    let [r, g, b] = *color;
+                    apply(runtime, device, "static colour", move |device| async move {
+                        device.set_all_static(Rgb { r, g, b }).await.map(|_| ())
+                    });
+                }
+            });
+        });
+    }
+}
@@ -0,0 +1,358 @@
+use crate::config::{Colour, IconTheme, Orientation};
+
+/// Glyphs are drawn on a 16x16 grid and scaled to whatever size is asked for.
+const GRID: u32 = 16;
+/// The size tray hosts ask for unless told otherwise.
+pub const DEFAULT_SIZE: u32 = GRID;
+/// Blank steps after the charging highlight reaches the end of the fill, before it starts over.
+const SWEEP_PAUSE: u32 = 2;
+/// How far the charging highlight moves the fill colour towards white.
+const SWEEP_LIGHTEN: f32 = 0.4;
+
+/// A battery filled to `value` percent. `fill` overrides the theme's charge-level colour when
+/// set. `sweep` is a charging animation step: a lighter line that moves across the fill from its
+/// base to its edge, one cell per step.
+pub fn battery_glyph(
+    value: u8,
+    theme: &IconTheme,
+    outline_dim: f32,
+    fill: Option<Colour>,
+    sweep: Option<u32>,
+    size: u32,
+) -> ksni::Icon {
+    let fill = fill.unwrap_or_else(|| theme.fill_for(value));
+    let (alpha, r, g, b) = theme.outline;
+    let outline = ((f32::from(alpha) * outline_dim.clamp(0.0, 1.0)).round() as u8, r, g, b);
+    draw_icon(size, |set_px| {
+        let body = draw_battery_outline(theme, outline, &mut *set_px);
+
+        // Battery fill (inner area), growing away from the side opposite the nub.
+        let (inner_x0, inner_y0) = (body.0 + 1, body.1 + 1);
+        let (inner_x1, inner_y1) = (body.2 - 1, body.3 - 1);
+        let inner_length = match theme.orientation {
+            Orientation::Horizontal => inner_x1 - inner_x0 + 1,
+            Orientation::Vertical => inner_y1 - inner_y0 + 1,
+        };
+        let filled = (value.min(100) as u32 * inner_length) / 100;
+        if filled > 0 {
+            let (x0, y0, x1, y1) = match theme.orientation {
+                Orientation::Horizontal => (inner_x0, inner_y0, inner_x0 + filled - 1, inner_y1),
+                Orientation::Vertical => (inner_x0, inner_y1 + 1 - filled, inner_x1, inner_y1),
+            };
+            let highlight = sweep.map(|step| step % (filled + SWEEP_PAUSE));
+            for x in x0..=x1 {
+                for y in y0..=y1 {
+                    let offset = match theme.orientation {
+                        Orientation::Horizontal => x - x0,
+                        Orientation::Vertical => y1 - y,
+                    };
+                    if highlight == Some(offset) {
+                        set_px(x, y, lighten(fill));
+                    } else {
+                        set_px(x, y, fill);
+                    }
+                }
+            }
+        }
+    })
+}
+
+fn lighten((alpha, r, g, b): Colour) -> Colour {
+    let channel = |value: u8| value + (f32::from(255 - value) * SWEEP_LIGHTEN).round() as u8;
+    (alpha, channel(r), channel(g), channel(b))
+}
+
+/// A plug, for devices that are connected but have no battery to report.
+pub fn connected_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    let outline = theme.outline;
+    draw_icon(size, |set_px| {
+        for y in 1..=4 {
+            set_px(5, y, outline);
+            set_px(10, y, outline);
+        }
+        for x in 3..=12 {
+            for y in 5..=10 {
+                set_px(x, y, outline);
+            }
+        }
+        for y in 11..=14 {
+            set_px(7, y, outline);
+            set_px(8, y, outline);
+        }
+    })
+}
+
+/// A faded, empty battery.
+pub fn no_device_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    let (_, r, g, b) = theme.outline;
+    draw_icon(size, |set_px| {
+        draw_battery_outline(theme, (110, r, g, b), &mut *set_px);
+    })
+}
+
+/// An empty battery crossed out.
+pub fn disconnected_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    draw_icon(size, |set_px| {
+        let body = draw_battery_outline(theme, theme.outline, &mut *set_px);
+        let size = (body.2 - body.0).min(body.3 - body.1) - 2;
+        for i in 0..=size {
+            set_px(body.0 + 1 + i, body.1 + 1 + i, theme.low);
+            set_px(body.0 + 1 + i, body.1 + 1 + size - i, theme.low);
+        }
+    })
+}
+
+pub fn mouse_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    let colour = theme.outline;
+    draw_icon(size, |set_px| {
+        draw_outlined_rect(4, 1, 11, 14, colour, &mut *set_px);
+        // Button split and scroll wheel.
+        for y in 2..=5 {
+            set_px(7, y, colour);
+            set_px(8, y, colour);
+        }
+        for x in 5..=10 {
+            set_px(x, 6, colour);
+        }
+    })
+}
+
+pub fn keyboard_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    let colour = theme.outline;
+    draw_icon(size, |set_px| {
+        draw_outlined_rect(0, 3, 15, 12, colour, &mut *set_px);
+        for x in (2..=13).step_by(2) {
+            set_px(x, 5, colour);
+            set_px(x, 7, colour);
+        }
+        for x in 4..=11 {
+            set_px(x, 10, colour);
+        }
+    })
+}
+
+pub fn headset_glyph(theme: &IconTheme, size: u32) -> ksni::Icon {
+    let colour = theme.outline;
+    draw_icon(size, |set_px| {
+        // Headband.
+        for x in 5..=10 {
+            set_px(x, 2, colour);
+        }
+        set_px(4, 3, colour);
+        set_px(11, 3, colour);
+        for y in 4..=8 {
+            set_px(3, y, colour);
+            set_px(12, y, colour);
+        }
+        // Ear cups.
+        for y in 8..=13 {
+            for x in 1..=4 {
+                set_px(x, y, colour);
+                set_px(x + 10, y, colour);
+            }
+        }
+    })
+}
+
+fn draw_icon(size: u32, draw: impl FnOnce(&mut dyn FnMut(u32, u32, Colour))) -> ksni::Icon {
+    let mut data = vec![0u8; (size * size * 4) as usize];
+    // Each grid cell becomes a block of pixels; below the grid size, neighbouring cells share one.
+    let span = |cell: u32| {
+        let start = cell * size / GRID;
+        start..((cell + 1) * size / GRID).max(start + 1).min(size)
+    };
+
+    let mut set_px = |x: u32, y: u32, color: Colour| {
+        if x >= GRID || y >= GRID {
+            return;
+        }
+        for py in span(y) {
+            for px in span(x) {
+                let idx = ((py * size + px) * 4) as usize;
+                data[idx..idx + 4].copy_from_slice(&[color.0, color.1, color.2, color.3]);
+            }
+        }
+    };
+    draw(&mut set_px);
+
+    ksni::Icon {
+        width: size as i32,
+        height: size as i32,
+        data,
+    }
+}
+
+/// Draws the battery body and terminal nub, returning the body as (x0, y0, x1, y1).
+fn draw_battery_outline(
+    theme: &IconTheme,
+    colour: Colour,
+    mut set_px: impl FnMut(u32, u32, Colour),
+) -> (u32, u32, u32, u32) {
+    let (body, nub) = match theme.orientation {
+        Orientation::Horizontal => ((1, 4, 12, 12), (13, 7, 14, 9)),
+        Orientation::Vertical => ((4, 3, 12, 14), (7, 1, 9, 2)),
+    };
+    draw_outlined_rect(body.0, body.1, body.2, body.3, colour, &mut set_px);
+    draw_outlined_rect(nub.0, nub.1, nub.2, nub.3, colour, &mut set_px);
+    body
+}
+
+fn draw_outlined_rect(
+    x0: u32,
+    y0: u32,
+    x1: u32,
+    y1: u32,
+    colour: Colour,
+    mut set_function: impl FnMut(u32, u32, Colour),
+) {
+    let (min_x, max_x) = if x0 <= x1 { (x0, x1) } else { (x1, x0) };
+    let (min_y, max_y) = if y0 <= y1 { (y0, y1) } else { (y1, y0) };
+
+    for x in min_x..=max_x {
+        set_function(x, min_y, colour);
+        set_function(x, max_y, colour);
+    }
+
+    for y in min_y..=max_y {
+        set_function(min_x, y, colour);
+        set_function(max_x, y, colour);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TRANSPARENT: Colour = (0, 0, 0, 0);
+
+    fn pixel(icon: &ksni::Icon, x: u32, y: u32) -> Colour {
+        let idx = ((y * icon.width as u32 + x) * 4) as usize;
+        let px = &icon.data[idx..idx + 4];
+        (px[0], px[1], px[2], px[3])
+    }
+
+    #[test]
+    fn battery_glyph_draws_the_outline_at_every_level() {
+        let theme = IconTheme::default();
+        for value in [0, 25, 50, 75, 100] {
+            let icon = battery_glyph(value, &theme, 1.0, None, None, DEFAULT_SIZE);
+            // Body corners, then the nub's.
+            for (x, y) in [(1, 4), (12, 4), (1, 12), (12, 12), (13, 7), (14, 9)] {
+                assert_eq!(pixel(&icon, x, y), theme.outline, "{value}% at ({x}, {y})");
+            }
+        }
+    }
+
+    #[test]
+    fn battery_glyph_fill_width_follows_the_percentage() {
+        let theme = IconTheme::default();
+        for value in [0u8, 25, 50, 75, 100] {
+            let icon = battery_glyph(value, &theme, 1.0, None, None, DEFAULT_SIZE);
+            // The horizontal body has ten inner columns, starting at x = 2.
+            let filled = u32::from(value) * 10 / 100;
+            for x in 2..12 {
+                let expected = if x < 2 + filled { theme.fill_for(value) } else { TRANSPARENT };
+                for y in 5..12 {
+                    assert_eq!(pixel(&icon, x, y), expected, "{value}% at ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn battery_glyph_fills_vertical_batteries_from_the_bottom() {
+        let theme = IconTheme { orientation: Orientation::Vertical, ..IconTheme::default() };
+        let icon = battery_glyph(50, &theme, 1.0, None, None, DEFAULT_SIZE);
+        // Ten inner rows, y = 4..=13; half of them are filled from the bottom up.
+        assert_eq!(pixel(&icon, 8, 13), theme.medium);
+        assert_eq!(pixel(&icon, 8, 9), theme.medium);
+        assert_eq!(pixel(&icon, 8, 8), TRANSPARENT);
+        assert_eq!(pixel(&icon, 4, 3), theme.outline);
+        assert_eq!(pixel(&icon, 7, 1), theme.outline);
+    }
+
+    #[test]
+    fn battery_glyph_fill_stays_inside_the_outline() {
+        for orientation in [Orientation::Horizontal, Orientation::Vertical] {
+            let theme = IconTheme { orientation, ..IconTheme::default() };
+            let mut body = (0, 0, 0, 0);
+            let outline_only = draw_icon(DEFAULT_SIZE, |set_px| {
+                body = draw_battery_outline(&theme, theme.outline, &mut *set_px);
+            });
+            let (x0, y0, x1, y1) = body;
+            // Values past 100 must not spill either.
+            for value in [0, 100, 255] {
+                let icon = battery_glyph(value, &theme, 1.0, None, None, DEFAULT_SIZE);
+                for y in 0..16 {
+                    for x in 0..16 {
+                        let inside = x > x0 && x < x1 && y > y0 && y < y1;
+                        let expected = if inside && value > 0 {
+                            theme.fill_for(value)
+                        } else {
+                            pixel(&outline_only, x, y)
+                        };
+                        assert_eq!(pixel(&icon, x, y), expected, "{value}% at ({x}, {y})");
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn battery_glyph_applies_fill_override_and_outline_dim() {
+        let theme = IconTheme::default();
+        let fill = (255, 1, 2, 3);
+        let icon = battery_glyph(100, &theme, 0.5, Some(fill), None, DEFAULT_SIZE);
+        assert_eq!(pixel(&icon, 2, 5), fill);
+        assert_eq!(pixel(&icon, 11, 11), fill);
+        let (alpha, r, g, b) = theme.outline;
+        assert_eq!(pixel(&icon, 1, 4), (alpha / 2 + 1, r, g, b));
+    }
+
+    #[test]
+    fn charging_sweep_lightens_one_line_of_the_fill() {
+        let theme = IconTheme::default();
+        let fill = theme.fill_for(50);
+        let lighter = lighten(fill);
+        assert_ne!(lighter, fill);
+        // Half of the ten inner columns are filled, so the cycle is five steps plus the pause.
+        for step in 0..7 {
+            let icon = battery_glyph(50, &theme, 1.0, None, Some(step), DEFAULT_SIZE);
+            for x in 2..7 {
+                let expected = if x - 2 == step { lighter } else { fill };
+                for y in 5..12 {
+                    assert_eq!(pixel(&icon, x, y), expected, "step {step} at ({x}, {y})");
+                }
+            }
+        }
+        let wrapped = battery_glyph(50, &theme, 1.0, None, Some(7), DEFAULT_SIZE);
+        assert_eq!(pixel(&wrapped, 2, 8), lighter);
+    }
+
+    #[test]
+    fn outlined_rect_normalises_its_corners() {
+        let colour = (255, 9, 9, 9);
+        let icon = draw_icon(DEFAULT_SIZE, |set_px| draw_outlined_rect(10, 9, 3, 2, colour, set_px));
+        for (x, y) in [(3, 2), (10, 2), (3, 9), (10, 9), (6, 2), (3, 5)] {
+            assert_eq!(pixel(&icon, x, y), colour, "({x}, {y})");
+        }
+        assert_eq!(pixel(&icon, 6, 5), TRANSPARENT);
+        assert_eq!(pixel(&icon, 2, 2), TRANSPARENT);
+    }
+
+    #[test]
+    fn glyphs_scale_each_grid_cell_to_a_block() {
+        let theme = IconTheme::default();
+        let small = battery_glyph(50, &theme, 1.0, None, None, DEFAULT_SIZE);
+        let large = battery_glyph(50, &theme, 1.0, None, None, DEFAULT_SIZE * 2);
+        assert_eq!((large.width, large.height), (32, 32));
+        for y in 0..DEFAULT_SIZE {
+            for x in 0..DEFAULT_SIZE {
+                let expected = pixel(&small, x, y);
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    assert_eq!(pixel(&large, x * 2 + dx, y * 2 + dy), expected, "({x}, {y})");
+                }
+            }
+        }
+    }
+}
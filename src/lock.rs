@@ -0,0 +1,203 @@
+use std::env;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use fs2::FileExt;
+use log::{info, warn};
+
+/// How often, and how far apart, a lock left by a dead process is retried before giving up.
+const RECLAIM_ATTEMPTS: u32 = 10;
+const RECLAIM_DELAY: Duration = Duration::from_millis(100);
+
+pub fn acquire_single_instance_lock() -> io::Result<File> {
+    acquire_lock_at(&lock_file_path())
+}
+
+fn acquire_lock_at(lock_path: &Path) -> io::Result<File> {
+    let mut file = open_lock_file(lock_path)?;
+    if let Err(err) = file.try_lock_exclusive() {
+        match read_pid(&mut file) {
+            Some(pid) if !process_exists(pid) => {
+                // fs2 locks are released when their owner exits, but some filesystems take a
+                // moment to notice. Retrying on this descriptor rather than unlinking the file
+                // keeps every starting instance contending for the same inode.
+                warn!(
+                    "Lock file {} is held by pid {pid} which is no longer running; waiting for it",
+                    lock_path.display()
+                );
+                reclaim(&file)?;
+            }
+            Some(pid) => {
+                info!("Lock file {} is held by running pid {pid}", lock_path.display());
+                return Err(err);
+            }
+            None => {
+                info!("Lock file {} is held by an unknown process", lock_path.display());
+                return Err(err);
+            }
+        }
+    }
+    write_pid(&mut file)?;
+    Ok(file)
+}
+
+fn reclaim(file: &File) -> io::Result<()> {
+    let mut attempts = 1;
+    loop {
+        thread::sleep(RECLAIM_DELAY);
+        match file.try_lock_exclusive() {
+            Ok(()) => return Ok(()),
+            Err(err) if attempts >= RECLAIM_ATTEMPTS => return Err(err),
+            Err(_) => attempts += 1,
+        }
+    }
+}
+
+/// Replaces the file's contents with this process's PID. Only called once the lock is held, so
+/// an instance that lost the race never overwrites the winner's PID. The PID goes out in one
+/// write and is synced, so a reader sees either nothing (and treats the owner as unknown) or the
+/// whole number, never a mix of old and new digits.
+fn write_pid(file: &mut File) -> io::Result<()> {
+    let pid = format!("{}\n", std::process::id());
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(pid.as_bytes())?;
+    file.flush()?;
+    file.sync_data()
+}
+
+/// Clears the PID and unlocks, leaving the file in place. Removing it would let a new instance
+/// lock a fresh file at the same path while another still held the old, unlinked one.
+pub fn release_single_instance_lock(file: File) {
+    if let Err(err) = file.set_len(0) {
+        warn!("Failed to clear lock file: {err}");
+    }
+    if let Err(err) = FileExt::unlock(&file) {
+        warn!("Failed to unlock lock file: {err}");
+    }
+}
+
+fn open_lock_file(lock_path: &Path) -> io::Result<File> {
+    OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(lock_path)
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "cannot open lock file {} ({err}); check that its directory exists and is writable",
+                    lock_path.display()
+                ),
+            )
+        })
+}
+
+fn read_pid(file: &mut File) -> Option<u32> {
+    let mut contents = String::new();
+    file.seek(SeekFrom::Start(0)).ok()?;
+    file.read_to_string(&mut contents).ok()?;
+    contents.trim().parse().ok()
+}
+
+fn process_exists(pid: u32) -> bool {
+    Path::new("/proc").join(pid.to_string()).exists()
+}
+
+fn lock_file_path() -> PathBuf {
+    match env::var_os("XDG_RUNTIME_DIR") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir).join("rusty-razer-tray.lock"),
+        // /tmp is shared between users, so keep each user's lock separate.
+        _ => fallback_lock_file_path(),
+    }
+}
+
+#[cfg(unix)]
+fn fallback_lock_file_path() -> PathBuf {
+    let uid = unsafe { libc::getuid() };
+    PathBuf::from(format!("/tmp/rusty-razer-tray-{uid}.lock"))
+}
+
+#[cfg(not(unix))]
+fn fallback_lock_file_path() -> PathBuf {
+    env::temp_dir().join("rusty-razer-tray.lock")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+
+    fn temp_lock_path(name: &str) -> PathBuf {
+        let pid = std::process::id();
+        let path = env::temp_dir().join(format!("rusty-razer-tray-test-{pid}-{name}.lock"));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn reads_the_pid_and_ignores_anything_else() {
+        let path = temp_lock_path("read-pid");
+        let mut file = open_lock_file(&path).unwrap();
+        assert_eq!(read_pid(&mut file), None);
+        fs::write(&path, "1234\n").unwrap();
+        assert_eq!(read_pid(&mut file), Some(1234));
+        fs::write(&path, "12ab").unwrap();
+        assert_eq!(read_pid(&mut file), None);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn only_running_processes_exist() {
+        assert!(process_exists(std::process::id()));
+        // Above the kernel's PID limit, so never a live process.
+        assert!(!process_exists(u32::MAX));
+    }
+
+    #[test]
+    fn takes_over_a_file_left_by_a_dead_process() {
+        let path = temp_lock_path("stale");
+        fs::write(&path, format!("{}\n", u32::MAX)).unwrap();
+        let mut file = acquire_lock_at(&path).unwrap();
+        assert_eq!(read_pid(&mut file), Some(std::process::id()));
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn release_leaves_an_empty_unlocked_file() {
+        let path = temp_lock_path("release");
+        release_single_instance_lock(acquire_lock_at(&path).unwrap());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "");
+        let again = acquire_lock_at(&path).unwrap();
+        release_single_instance_lock(again);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_lock_held_by_a_running_process() {
+        let path = temp_lock_path("held");
+        let _held = acquire_lock_at(&path).unwrap();
+        assert!(acquire_lock_at(&path).is_err());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn keeps_the_file_when_a_dead_owner_still_holds_the_lock() {
+        let path = temp_lock_path("dead-holder");
+        let held = open_lock_file(&path).unwrap();
+        held.try_lock_exclusive().unwrap();
+        fs::write(&path, format!("{}\n", u32::MAX)).unwrap();
+        let inode = |path: &Path| std::os::unix::fs::MetadataExt::ino(&fs::metadata(path).unwrap());
+        let before = inode(&path);
+        assert!(acquire_lock_at(&path).is_err());
+        assert_eq!(inode(&path), before);
+        fs::remove_file(&path).unwrap();
+    }
+}
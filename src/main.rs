@@ -1,19 +1,159 @@
-mod openrazer;
+mod cli;
+#[cfg(feature = "audio")]
+mod audio;
+#[cfg(feature = "lighting")]
+mod battery_saver;
+mod clipboard;
+mod commands;
+mod config;
+mod config_watch;
+#[cfg(feature = "reqwest")]
+mod device_image;
+#[cfg(feature = "dpi")]
+mod dpi;
+#[cfg(feature = "lighting")]
+mod effects;
+mod eta;
+mod icons;
+#[cfg(feature = "gui")]
+mod gui;
+mod lock;
+mod notifications;
+mod polling;
+mod presence;
+#[cfg(feature = "lighting")]
+mod profiles;
+mod service;
+mod smoothing;
+#[cfg(feature = "temperature")]
+mod temperature;
+mod title;
+mod visibility;
 
+#[cfg(feature = "lighting")]
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::env;
-use std::fs::OpenOptions;
+use std::fs::{File, OpenOptions};
 use std::io;
 #[cfg(unix)]
 use std::os::unix::io::AsRawFd;
-use std::time::Duration;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use fs2::FileExt;
-use ksni::menu::{Disposition, StandardItem};
+use cli::Args;
+use clipboard::Clipboard;
+#[cfg(feature = "dpi")]
+use config::DpiStagesConfig;
+#[cfg(feature = "lighting")]
+use config::{IconColor, LightingProfile};
+use config::{BatteryConfig, Colour, Config, IconStyle, IconTheme};
+#[cfg(feature = "temperature")]
+use config::TemperatureConfig;
+#[cfg(feature = "lighting")]
+use effects::{EffectStore, SavedEffect, SharedEffectStore};
+use eta::ChargeEstimator;
+use ksni::menu::{CheckmarkItem, Disposition, StandardItem, SubMenu};
 use ksni::{Tray, TrayMethods};
-use openrazer::Manager;
+use log::{debug, error, info, warn};
+use polling::AdaptiveInterval;
+use presence::BatteryCache;
+use rusty_razer_tray::openrazer::{BatterySummary, Device, DeviceEvent, DeviceType, Manager};
+#[cfg(feature = "dpi")]
+use rusty_razer_tray::openrazer::Dpi;
+#[cfg(feature = "lighting")]
+use rusty_razer_tray::openrazer::{LedEffect, LedId, RazerError, Rgb};
+use smoothing::BatterySmoother;
+#[cfg(feature = "temperature")]
+use temperature::TemperatureLighting;
+use title::TitleTemplate;
+use visibility::VisibilityDebounce;
+use tokio::sync::{broadcast, watch, Notify};
+use zbus::zvariant::OwnedObjectPath;
+#[cfg(feature = "lighting")]
+use tokio::sync::Mutex;
 
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+/// Choices offered under "Low battery warning at".
+const LOW_BATTERY_THRESHOLDS: [u8; 4] = [5, 10, 15, 25];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum IconState {
+    Battery(u8),
+    /// Razer devices are present but none of them report a battery.
+    Connected,
+    NoDevice,
+    /// The device is listed, but its wireless link is down: switched off or out of range.
+    OutOfRange,
+    /// The OpenRazer daemon could not be reached.
+    Disconnected,
+}
+
+#[derive(Clone)]
 struct BatteryTray {
-    counter: u8,
+    state: IconState,
+    charging: bool,
+    /// Advanced once per poll while charging, to move the highlight across the battery fill.
+    animation_phase: u32,
+    charge_eta: Option<Duration>,
+    /// Per-cell levels, e.g. "L 80% / R 75%", when the device has more than one battery.
+    battery_cells: Option<String>,
+    /// The title rendered from `title_format` on the last poll, if one is configured.
+    custom_title: Option<String>,
+    /// Scales the outline alpha, so the icon looks dimmer when the lighting is turned down.
+    outline_dim: f32,
+    /// The device's static lighting colour, used as the fill when `icon.icon_color = "device"`.
+    #[cfg(feature = "lighting")]
+    device_fill: Option<Colour>,
+    device_type: Option<DeviceType>,
+    /// The device's downscaled product picture, shown instead of the type glyph when set.
+    #[cfg(feature = "reqwest")]
+    device_image: Option<ksni::Icon>,
+    daemon_version: Option<String>,
+    /// Firmware of `device`, when it reports one.
+    firmware: Option<String>,
+    /// The percentage at which `device` itself warns of a low battery, if it has the setting.
+    low_battery_threshold: Option<u8>,
+    /// The device the battery readout is for, or the first device found when none has a battery.
+    device: Option<Arc<Device>>,
+    #[cfg(feature = "lighting")]
+    keyboard: Option<Arc<Device>>,
+    #[cfg(feature = "lighting")]
+    game_mode: bool,
+    /// The first keyboard with profile LEDs, which needn't be the one with game mode.
+    #[cfg(feature = "lighting")]
+    profile_led_keyboard: Option<Arc<Device>>,
+    /// State of `profile_led_keyboard`'s red, green and blue profile LEDs, for the ones it has.
+    #[cfg(feature = "lighting")]
+    profile_leds: Vec<(LedId, bool)>,
+    /// The running audio visualizer, shared so clones of the tray don't start a second one.
+    #[cfg(feature = "audio")]
+    visualizer: Option<Arc<audio::Visualizer>>,
+    /// Active stage (1-based) and stage list of the current device, if it has DPI stages.
+    #[cfg(feature = "dpi")]
+    dpi_stages: Option<(u8, Vec<Dpi>)>,
+    /// Current DPI of the device, when it is a mouse.
+    #[cfg(feature = "dpi")]
+    dpi: Option<Dpi>,
+    theme: IconTheme,
+    /// Serialises brightness read-modify-writes per device object path.
+    #[cfg(feature = "lighting")]
+    brightness_locks: HashMap<String, Arc<Mutex<()>>>,
+    clipboard: Arc<dyn Clipboard>,
+    #[cfg(feature = "lighting")]
+    white: Rgb,
+    #[cfg(feature = "lighting")]
+    profiles: Arc<BTreeMap<String, LightingProfile>>,
+    /// Where applied effects are recorded, when `lighting.persist_effects` is on.
+    #[cfg(feature = "lighting")]
+    effects: Option<SharedEffectStore>,
+    /// The poll loop's manager once it has connected, so menu actions use the same bus and call
+    /// timeout.
+    #[cfg(feature = "lighting")]
+    manager: Option<Arc<Manager>>,
+    /// Wakes the poll loop early for an immediate re-read.
+    refresh: Arc<Notify>,
 }
 
 impl Tray for BatteryTray {
@@ -23,79 +163,1224 @@ impl Tray for BatteryTray {
 
     // At least on gnome this isn't showing on hover so just focusing on the icon itself
     fn title(&self) -> String {
-        format!("Battery {}%", self.counter)
+        if let (IconState::Battery(_), Some(title)) = (self.state, &self.custom_title) {
+            return title.clone();
+        }
+        match self.state {
+            IconState::Battery(percent) if self.charging => {
+                format!("Battery {percent}% (charging)")
+            }
+            IconState::Battery(percent) => format!("Battery {percent}%"),
+            _ => self.status_text().to_string(),
+        }
+    }
+
+    fn tool_tip(&self) -> ksni::ToolTip {
+        let mut lines = Vec::new();
+        lines.extend(self.charge_eta.map(eta::format_eta));
+        #[cfg(feature = "dpi")]
+        lines.extend(self.dpi.as_ref().map(dpi::tooltip_line));
+        ksni::ToolTip {
+            title: self.title(),
+            description: lines.join("\n"),
+            ..Default::default()
+        }
+    }
+
+    // Hosts prefer a themed icon name over the pixmap, so only one of the two is ever set.
+    fn icon_name(&self) -> String {
+        match self.theme.style {
+            IconStyle::Symbolic => symbolic_icon_name(self.state, self.charging),
+            IconStyle::Color => String::new(),
+        }
     }
 
     fn icon_pixmap(&self) -> Vec<ksni::Icon> {
-        vec![render_digit_icon(self.counter)]
+        if self.theme.style == IconStyle::Symbolic {
+            return Vec::new();
+        }
+        #[cfg(feature = "lighting")]
+        let fill = self.device_fill;
+        #[cfg(not(feature = "lighting"))]
+        let fill = None;
+        let sweep = self.charging_sweep();
+        let mut icons = vec![render_icon(self.state, &self.theme, self.outline_dim, fill, sweep)];
+        if let IconState::Battery(value) = self.state {
+            let size = icons::DEFAULT_SIZE;
+            #[cfg(feature = "reqwest")]
+            if let Some(image) = &self.device_image {
+                icons.push(image.clone());
+                return icons;
+            }
+            icons.push(match &self.device_type {
+                Some(DeviceType::Mouse) => icons::mouse_glyph(&self.theme, size),
+                Some(DeviceType::Keyboard) => icons::keyboard_glyph(&self.theme, size),
+                Some(DeviceType::Headset) => icons::headset_glyph(&self.theme, size),
+                _ => icons::battery_glyph(value, &self.theme, self.outline_dim, fill, sweep, size),
+            });
+        }
+        icons
+    }
+
+    fn watcher_online(&self) {
+        info!("System tray host is back, re-registering the tray icon");
+    }
+
+    // ksni re-registers by itself when the watcher reappears, as long as the service is still
+    // running, so a panel restart only needs the tray to stay alive in the meantime.
+    fn watcher_offline(&self, reason: ksni::OfflineReason) -> bool {
+        warn!("System tray host went away ({reason:?}); waiting for it to come back");
+        true
     }
 
     fn menu(&self) -> Vec<ksni::menu::MenuItem<Self>> {
-        vec!(ksni::MenuItem::Standard(StandardItem {
-            label: format!("Battery Level {}%", self.counter),
-            enabled: false,
-            visible: true,
-            icon_name: "".to_string(),
-            icon_data: vec![],
-            shortcut: vec![],
-            disposition: Disposition::Normal,
-            activate: Box::new(|_this| {}),
-        }))
+        let battery = match (self.state, &self.battery_cells) {
+            (IconState::Battery(_), Some(cells)) if self.charging => {
+                format!("Battery Level {cells} (charging)")
+            }
+            (IconState::Battery(_), Some(cells)) => format!("Battery Level {cells}"),
+            (IconState::Battery(percent), None) if self.charging => {
+                format!("Battery Level {percent}% (charging)")
+            }
+            (IconState::Battery(percent), None) => format!("Battery Level {percent}%"),
+            _ => self.status_text().to_string(),
+        };
+        let daemon = format!(
+            "OpenRazer: {}",
+            self.daemon_version.as_deref().unwrap_or("unknown")
+        );
+        let mut items = vec![info_item(battery), info_item(daemon)];
+        if let Some(firmware) = &self.firmware {
+            items.push(info_item(format!("Firmware: {firmware}")));
+        }
+        items.push(
+            StandardItem {
+                label: "Refresh devices".to_string(),
+                activate: Box::new(|this: &mut Self| this.refresh.notify_one()),
+                ..Default::default()
+            }
+            .into(),
+        );
+        #[cfg(feature = "lighting")]
+        if self.keyboard.is_some() {
+            items.push(
+                CheckmarkItem {
+                    label: "Game Mode".to_string(),
+                    checked: self.game_mode,
+                    activate: Box::new(|this: &mut Self| {
+                        let Some(device) = this.keyboard.clone() else {
+                            return;
+                        };
+                        let enabled = !this.game_mode;
+                        this.game_mode = enabled;
+                        let refresh = this.refresh.clone();
+                        tokio::spawn(async move {
+                            if let Err(err) = device.set_game_mode(enabled).await {
+                                warn!("Failed to set game mode: {err}");
+                            }
+                            refresh.notify_one();
+                        });
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "lighting")]
+        if !self.profile_leds.is_empty() {
+            let submenu = self
+                .profile_leds
+                .iter()
+                .map(|&(led, on)| {
+                    CheckmarkItem {
+                        label: profile_led_label(led).to_string(),
+                        checked: on,
+                        activate: Box::new(move |this: &mut Self| this.toggle_profile_led(led)),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect();
+            items.push(
+                SubMenu {
+                    label: "Profile LEDs".to_string(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "audio")]
+        if self.matrix_keyboard().is_some() {
+            items.push(
+                CheckmarkItem {
+                    label: "Audio visualizer".to_string(),
+                    checked: self.visualizer.is_some(),
+                    activate: Box::new(|this: &mut Self| this.toggle_visualizer()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        if let Some(current) = self.low_battery_threshold {
+            let submenu = LOW_BATTERY_THRESHOLDS
+                .iter()
+                .map(|&percent| {
+                    CheckmarkItem {
+                        label: format!("{percent}%"),
+                        checked: percent == current,
+                        activate: Box::new(move |this: &mut Self| {
+                            this.set_low_battery_threshold(percent)
+                        }),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect();
+            items.push(
+                SubMenu {
+                    label: "Low battery warning at".to_string(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "dpi")]
+        if let Some((active, stages)) = &self.dpi_stages {
+            let submenu = stages
+                .iter()
+                .enumerate()
+                .map(|(index, dpi)| {
+                    let stage = index as u8 + 1;
+                    CheckmarkItem {
+                        label: dpi::stage_label(dpi),
+                        checked: stage == *active,
+                        activate: Box::new(move |this: &mut Self| this.select_dpi_stage(stage)),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect();
+            items.push(
+                SubMenu {
+                    label: "DPI stages".to_string(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+            items.push(
+                StandardItem {
+                    label: "Next DPI stage".to_string(),
+                    activate: Box::new(|this: &mut Self| this.next_dpi_stage()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        if self.device.is_some() {
+            items.push(
+                StandardItem {
+                    label: "Copy serial".to_string(),
+                    activate: Box::new(|this: &mut Self| this.copy_serial()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "gui")]
+        if self.device.is_some() {
+            items.push(
+                StandardItem {
+                    label: "Settings…".to_string(),
+                    activate: Box::new(|this: &mut Self| {
+                        if let Some(device) = this.device.clone() {
+                            gui::open(device, tokio::runtime::Handle::current());
+                        }
+                    }),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "lighting")]
+        if self.device.as_ref().is_some_and(|device| !device.supported_leds().is_empty()) {
+            items.push(
+                StandardItem {
+                    label: "Static white".to_string(),
+                    activate: Box::new(|this: &mut Self| this.set_static_white()),
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "lighting")]
+        if !self.profiles.is_empty() {
+            let submenu = self
+                .profiles
+                .keys()
+                .map(|name| {
+                    let name = name.clone();
+                    StandardItem {
+                        label: name.clone(),
+                        activate: Box::new(move |this: &mut Self| this.apply_profile(&name)),
+                        ..Default::default()
+                    }
+                    .into()
+                })
+                .collect();
+            items.push(
+                SubMenu {
+                    label: "Lighting profile".to_string(),
+                    submenu,
+                    ..Default::default()
+                }
+                .into(),
+            );
+        }
+        #[cfg(feature = "lighting")]
+        if let Some(device) = &self.device {
+            let mut leds: Vec<_> = device
+                .supported_leds()
+                .iter()
+                .filter(|(led, _)| device.supports_brightness(**led))
+                .map(|(led, name)| (*led, name.clone()))
+                .collect();
+            leds.sort_by(|a, b| a.1.cmp(&b.1));
+            for (led, name) in leds {
+                items.push(
+                    SubMenu {
+                        label: name,
+                        submenu: vec![
+                            brightness_item("Brightness +10%", led, 10.0),
+                            brightness_item("Brightness -10%", led, -10.0),
+                        ],
+                        ..Default::default()
+                    }
+                    .into(),
+                );
+            }
+        }
+        items
     }
 }
 
+impl BatteryTray {
+    fn status_text(&self) -> &'static str {
+        match self.state {
+            IconState::Battery(_) => "Battery",
+            IconState::Connected => "Razer device connected",
+            IconState::NoDevice => "No Razer devices found",
+            IconState::OutOfRange => "Device off or out of range",
+            IconState::Disconnected => "OpenRazer daemon not running",
+        }
+    }
+
+    /// The charging animation step, or `None` once the battery is full or unplugged.
+    fn charging_sweep(&self) -> Option<u32> {
+        match self.state {
+            IconState::Battery(percent) if self.charging && percent < 100 => {
+                Some(self.animation_phase)
+            }
+            _ => None,
+        }
+    }
+
+    fn copy_serial(&self) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let clipboard = self.clipboard.clone();
+        tokio::spawn(async move {
+            let serial = match device.get_serial().await {
+                Ok(serial) => serial,
+                Err(err) => {
+                    warn!("Failed to read device serial: {err}");
+                    return;
+                }
+            };
+            match tokio::task::spawn_blocking(move || clipboard.copy(&serial)).await {
+                Ok(Ok(())) => {}
+                Ok(Err(err)) => warn!("Failed to copy serial to the clipboard: {err}"),
+                Err(err) => warn!("Clipboard task failed: {err}"),
+            }
+        });
+    }
+
+    fn set_low_battery_threshold(&mut self, percent: u8) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        self.low_battery_threshold = Some(percent);
+        let refresh = self.refresh.clone();
+        tokio::spawn(async move {
+            // The daemon takes the threshold as a double but reports it back as a byte.
+            if let Err(err) = device.set_low_battery_threshold(f64::from(percent)).await {
+                warn!("Failed to set the low battery warning to {percent}%: {err}");
+            }
+            refresh.notify_one();
+        });
+    }
+
+    #[cfg(feature = "dpi")]
+    fn select_dpi_stage(&mut self, stage: u8) {
+        let (Some(device), Some((active, stages))) = (self.device.clone(), &mut self.dpi_stages)
+        else {
+            return;
+        };
+        *active = stage;
+        let stages = stages.clone();
+        let refresh = self.refresh.clone();
+        tokio::spawn(async move {
+            let Some(&dpi) = stages.get(usize::from(stage) - 1) else {
+                return;
+            };
+            if let Err(err) = device.set_dpi_stages(stage, stages).await {
+                warn!("Failed to select DPI stage {stage}: {err}");
+                return;
+            }
+            // Some mice clamp or ignore the stage value, so check what they actually run at.
+            if let Err(err) = device.verify_dpi(dpi).await {
+                warn!("DPI stage {stage} didn't apply as configured: {err}");
+            }
+            refresh.notify_one();
+        });
+    }
+
+    // Re-reads the stages rather than trusting the menu state, since the mouse's own DPI
+    // button may have moved the active stage since the last poll.
+    #[cfg(feature = "dpi")]
+    fn next_dpi_stage(&self) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let refresh = self.refresh.clone();
+        tokio::spawn(async move {
+            let (active, stages) = match device.get_dpi_stages().await {
+                Ok(stages) => stages,
+                Err(err) => {
+                    warn!("Failed to read DPI stages: {err}");
+                    return;
+                }
+            };
+            if stages.len() < 2 {
+                info!("Only one DPI stage is configured; nothing to cycle to");
+                return;
+            }
+            let next = active as usize % stages.len() + 1;
+            if let Err(err) = device.set_dpi_stages(next as u8, stages).await {
+                warn!("Failed to switch to DPI stage {next}: {err}");
+                return;
+            }
+            refresh.notify_one();
+        });
+    }
+
+    #[cfg(feature = "lighting")]
+    fn set_static_white(&self) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let white = self.white;
+        let effects = self.effects.clone();
+        tokio::spawn(async move {
+            // Only LEDs that took the colour are recorded, so a replug doesn't replay an effect
+            // that never showed.
+            let applied = match device.set_all_static(white).await {
+                Ok(applied) => applied,
+                Err(RazerError::PartialFailure { applied, leds }) => {
+                    for (name, err) in leds {
+                        warn!("Failed to set static white on the {name} LED: {err}");
+                    }
+                    applied
+                }
+                Err(err) => {
+                    warn!("Failed to set static white: {err}");
+                    return;
+                }
+            };
+            let (Some(effects), Ok(serial)) = (effects, device.get_serial().await) else {
+                return;
+            };
+            let mut effects = effects.lock().unwrap();
+            for name in &applied {
+                effects.record(&serial, name, SavedEffect::Static { color: white });
+            }
+        });
+    }
+
+    #[cfg(feature = "lighting")]
+    fn toggle_profile_led(&mut self, led: LedId) {
+        let Some(device) = self.profile_led_keyboard.clone() else {
+            return;
+        };
+        let Some((_, on)) = self.profile_leds.iter_mut().find(|(known, _)| *known == led) else {
+            return;
+        };
+        *on = !*on;
+        let on = *on;
+        let refresh = self.refresh.clone();
+        tokio::spawn(async move {
+            if let Err(err) = device.set_profile_led(led, on).await {
+                warn!("Failed to switch the {} profile LED: {err}", profile_led_label(led));
+            }
+            refresh.notify_one();
+        });
+    }
+
+    #[cfg(feature = "lighting")]
+    fn apply_profile(&self, name: &str) {
+        let Some(profile) = self.profiles.get(name).cloned() else {
+            return;
+        };
+        let Some(manager) = self.manager.clone() else {
+            warn!("Not connected to OpenRazer yet; can't apply lighting profile {name}");
+            return;
+        };
+        let name = name.to_string();
+        let effects = self.effects.clone();
+        tokio::spawn(async move {
+            if let Err(err) = profiles::apply(&manager, &profile, effects.as_ref()).await {
+                warn!("Failed to apply lighting profile {name}: {err}");
+            }
+        });
+    }
+
+    /// The device the audio visualizer runs on: the game mode keyboard, else the battery device,
+    /// as long as it can take per-key frames.
+    #[cfg(feature = "audio")]
+    fn matrix_keyboard(&self) -> Option<&Arc<Device>> {
+        self.keyboard
+            .iter()
+            .chain(&self.device)
+            .find(|device| device.has_feature("per_key_matrix"))
+    }
+
+    #[cfg(feature = "audio")]
+    fn toggle_visualizer(&mut self) {
+        if self.visualizer.take().is_some() {
+            return;
+        }
+        let Some(device) = self.matrix_keyboard().cloned() else {
+            return;
+        };
+        match audio::Visualizer::start(device) {
+            Ok(visualizer) => self.visualizer = Some(Arc::new(visualizer)),
+            Err(err) => warn!("Failed to start the audio visualizer: {err}"),
+        }
+    }
+
+    #[cfg(feature = "lighting")]
+    fn adjust_brightness(&mut self, led: LedId, delta: f64) {
+        let Some(device) = self.device.clone() else {
+            return;
+        };
+        let lock = self
+            .brightness_locks
+            .entry(device.object_path().to_string())
+            .or_default()
+            .clone();
+        // Hold the lock across the read and the write so quick repeated clicks all land.
+        tokio::spawn(async move {
+            let _guard = lock.lock().await;
+            let result = async {
+                let current = device.get_brightness(led).await?;
+                device
+                    .set_brightness(led, (current + delta).clamp(0.0, 100.0))
+                    .await
+            };
+            if let Err(err) = result.await {
+                warn!("Failed to adjust brightness: {err}");
+            }
+        });
+    }
+}
+
+fn info_item(label: String) -> ksni::menu::MenuItem<BatteryTray> {
+    ksni::MenuItem::Standard(StandardItem {
+        label,
+        enabled: false,
+        visible: true,
+        icon_name: "".to_string(),
+        icon_data: vec![],
+        shortcut: vec![],
+        disposition: Disposition::Normal,
+        activate: Box::new(|_this| {}),
+    })
+}
+
+#[cfg(feature = "lighting")]
+fn profile_led_label(led: LedId) -> &'static str {
+    match led {
+        LedId::KeymapRedLED => "Red",
+        LedId::KeymapGreenLED => "Green",
+        _ => "Blue",
+    }
+}
+
+#[cfg(feature = "lighting")]
+fn brightness_item(label: &str, led: LedId, delta: f64) -> ksni::menu::MenuItem<BatteryTray> {
+    StandardItem {
+        label: label.to_string(),
+        activate: Box::new(move |this: &mut BatteryTray| this.adjust_brightness(led, delta)),
+        ..Default::default()
+    }
+    .into()
+}
+
 fn main() {
-    println!("Starting rusty-razer-tray...");
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}");
+            std::process::exit(2);
+        }
+    };
+
+    if args.version {
+        build_runtime().block_on(commands::print_version());
+        return;
+    }
+
+    if args.install_service {
+        match service::install_service(args.force) {
+            Ok(path) => {
+                println!("Wrote {}", path.display());
+                println!("Enable it with: systemctl --user daemon-reload && systemctl --user enable --now rusty-razer-tray.service");
+            }
+            Err(err) => {
+                eprintln!("Failed to install service: {err}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    if args.capabilities {
+        if let Err(err) = build_runtime().block_on(commands::dump_capabilities(args.json)) {
+            eprintln!("Failed to read device capabilities: {err}");
+            std::process::exit(1);
+        }
+        return;
+    }
 
-    if let Err(err) = daemonize_background() {
-        eprintln!("Failed to run in background: {err}");
+    if let Some(serial) = &args.introspect {
+        if let Err(err) = build_runtime().block_on(commands::print_introspection(serial)) {
+            eprintln!("Failed to introspect {serial}: {err}");
+            std::process::exit(1);
+        }
         return;
     }
 
-    let runtime = tokio::runtime::Builder::new_current_thread()
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    info!("Starting rusty-razer-tray...");
+    let mut config = Config::load();
+    config.ignore_serials.extend(args.ignore);
+    notifications::init(&config.notifications);
+
+    #[cfg(feature = "lighting")]
+    if let Some(name) = &args.profile {
+        if let Err(err) = build_runtime().block_on(profiles::apply_named(&config, name)) {
+            error!("{err}");
+            std::process::exit(1);
+        }
+        return;
+    }
+
+    // Headless status lines go to stdout, so --status stays in the foreground.
+    if let Err(err) = daemonize_background(args.foreground || args.status) {
+        error!("Failed to run in background: {err}");
+        return;
+    }
+
+    // Taken before the runtime starts, since reclaiming a stale lock sleeps between attempts.
+    let runtime = build_runtime();
+    let lock_file = match lock::acquire_single_instance_lock() {
+        Ok(lock) => lock,
+        Err(err) => {
+            match runtime.block_on(presence::signal_running_instance()) {
+                Ok(()) => info!("Another instance is already running; asked it to say so"),
+                Err(_) => error!("Another instance is already running or lock failed: {err}"),
+            }
+            return;
+        }
+    };
+    runtime.block_on(async_main(config, args.status, lock_file));
+}
+
+fn build_runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
         .enable_io()
         .enable_time()
         .build()
-        .expect("failed to start tokio runtime");
-    runtime.block_on(async_main());
+        .expect("failed to start tokio runtime")
 }
 
-async fn async_main() {
-    let _lock_file = match acquire_single_instance_lock() {
-        Ok(lock) => lock,
+/// `headless` keeps running without a tray icon, printing status changes, when no tray host
+/// is available. `lock_file` is released on the way out.
+async fn async_main(config: Config, headless: bool, lock_file: File) {
+    let refresh = Arc::new(Notify::new());
+    #[cfg(feature = "lighting")]
+    let effects = config
+        .lighting
+        .persist_effects
+        .then(|| Arc::new(std::sync::Mutex::new(EffectStore::load())));
+    let new_tray = || BatteryTray {
+        state: IconState::NoDevice,
+        charging: false,
+        animation_phase: 0,
+        charge_eta: None,
+        battery_cells: None,
+        custom_title: None,
+        outline_dim: 1.0,
+        #[cfg(feature = "lighting")]
+        device_fill: None,
+        device_type: None,
+        #[cfg(feature = "reqwest")]
+        device_image: None,
+        daemon_version: None,
+        firmware: None,
+        low_battery_threshold: None,
+        device: None,
+        #[cfg(feature = "lighting")]
+        keyboard: None,
+        #[cfg(feature = "lighting")]
+        game_mode: false,
+        #[cfg(feature = "lighting")]
+        profile_led_keyboard: None,
+        #[cfg(feature = "lighting")]
+        profile_leds: Vec::new(),
+        #[cfg(feature = "audio")]
+        visualizer: None,
+        #[cfg(feature = "dpi")]
+        dpi_stages: None,
+        #[cfg(feature = "dpi")]
+        dpi: None,
+        theme: config.icon,
+        #[cfg(feature = "lighting")]
+        brightness_locks: HashMap::new(),
+        clipboard: Arc::from(clipboard::backend(config.clipboard)),
+        #[cfg(feature = "lighting")]
+        white: config.lighting.white,
+        #[cfg(feature = "lighting")]
+        profiles: Arc::new(config.lighting.profiles.clone()),
+        #[cfg(feature = "lighting")]
+        effects: effects.clone(),
+        #[cfg(feature = "lighting")]
+        manager: None,
+        refresh: refresh.clone(),
+    };
+    let handle = match new_tray().spawn().await {
+        Ok(handle) => TrayHandle::Tray(Arc::new(tokio::sync::Mutex::new(Shown::Visible(handle)))),
+        Err(err) if headless => {
+            warn!("No system tray host found ({err}); printing battery status instead");
+            let tray = new_tray();
+            println!("{}", tray.title());
+            TrayHandle::Headless(Arc::new(std::sync::Mutex::new(tray)))
+        }
         Err(err) => {
-            eprintln!("Another instance is already running or lock failed: {err}");
+            error!("No system tray host found; is your panel running? ({err})");
+            error!("Run with --status to print the battery level without a tray");
+            lock::release_single_instance_lock(lock_file);
             return;
         }
     };
 
-    let handle = BatteryTray { counter: 0 }.spawn().await.unwrap();
-    let manager = match Manager::new().await {
+    let (config_updates, config_changes) = watch::channel(config.clone());
+    let _config_watch = AbortOnDrop(
+        tokio::spawn(config_watch::watch(handle.clone(), config_updates)).abort_handle(),
+    );
+    let poll = run(
+        handle.clone(),
+        refresh,
+        config,
+        config_changes,
+        #[cfg(feature = "lighting")]
+        effects,
+    );
+    tokio::select! {
+        _ = poll => {}
+        signal = shutdown_signal() => info!("Received {signal}, shutting down"),
+    }
+    handle.shutdown().await;
+    lock::release_single_instance_lock(lock_file);
+}
+
+async fn run(
+    handle: TrayHandle,
+    refresh: Arc<Notify>,
+    config: Config,
+    config_changes: watch::Receiver<Config>,
+    #[cfg(feature = "lighting")] effects: Option<SharedEffectStore>,
+) {
+    let mut manager = match Manager::new().await {
         Ok(manager) => manager,
         Err(err) => {
-            eprintln!("Failed to connect to OpenRazer via D-Bus: {err}");
+            error!("Failed to connect to OpenRazer via D-Bus: {err}");
             std::future::pending::<()>().await;
             return;
         }
     };
 
-    tokio::spawn(async move {
-        let mut value = 0u8;
-        loop {
-            if let Some(percent) = read_battery_percent(&manager).await {
-                value = percent;
+    manager.set_call_timeout(Duration::from_millis(config.dbus.timeout_ms));
+    manager.set_battery_cache_ttl(Duration::from_millis(config.battery.cache_ttl_ms));
+    manager.set_device_order(config.device_order);
+    let manager = Arc::new(manager);
+    #[cfg(feature = "lighting")]
+    {
+        let manager = manager.clone();
+        handle.update(|tray| tray.manager = Some(manager)).await;
+    }
+
+    let batteries = BatteryCache::default();
+    let _presence = presence::register(batteries.clone()).await;
+    let _event_log = AbortOnDrop(tokio::spawn(log_device_events(manager.events())).abort_handle());
+
+    supervise_poll_task(PollContext {
+        manager,
+        handle,
+        batteries,
+        refresh,
+        config: config_changes,
+        #[cfg(feature = "lighting")]
+        effects,
+    })
+    .await;
+}
+
+#[cfg(unix)]
+async fn shutdown_signal() -> &'static str {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let (Ok(mut terminate), Ok(mut interrupt)) = (
+        signal(SignalKind::terminate()),
+        signal(SignalKind::interrupt()),
+    ) else {
+        warn!("Failed to install signal handlers");
+        return std::future::pending().await;
+    };
+    tokio::select! {
+        _ = terminate.recv() => "SIGTERM",
+        _ = interrupt.recv() => "SIGINT",
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> &'static str {
+    let _ = tokio::signal::ctrl_c().await;
+    "Ctrl-C"
+}
+
+async fn log_device_events(mut events: broadcast::Receiver<DeviceEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(DeviceEvent::DeviceAdded { serial }) => info!("Device {serial} connected"),
+            Ok(DeviceEvent::DeviceRemoved { serial }) => info!("Device {serial} disconnected"),
+            Ok(DeviceEvent::BatteryChanged { serial, percent }) => {
+                debug!("Device {serial} battery at {percent}%")
             }
-            let _ = handle.update(|tray| tray.counter = value).await;
-            tokio::time::sleep(Duration::from_millis(1000)).await;
+            Ok(DeviceEvent::ChargingChanged { serial, charging: true }) => {
+                info!("Device {serial} started charging")
+            }
+            Ok(DeviceEvent::ChargingChanged { serial, charging: false }) => {
+                info!("Device {serial} stopped charging")
+            }
+            Err(broadcast::error::RecvError::Lagged(missed)) => {
+                warn!("Device event log fell behind and missed {missed} events")
+            }
+            Err(broadcast::error::RecvError::Closed) => return,
         }
-    });
+    }
+}
+
+/// Where poll results go: the tray icon, or stdout when running headless with `--status`.
+#[derive(Clone)]
+enum TrayHandle {
+    Tray(Arc<tokio::sync::Mutex<Shown>>),
+    Headless(Arc<std::sync::Mutex<BatteryTray>>),
+}
 
-    std::future::pending::<()>().await;
+/// ksni can't hide an icon, so a hidden tray is shut down and its state kept here until it is
+/// spawned again.
+enum Shown {
+    Visible(ksni::Handle<BatteryTray>),
+    Hidden(Box<BatteryTray>),
+}
+
+impl TrayHandle {
+    async fn update(&self, f: impl FnOnce(&mut BatteryTray) + Send) {
+        match self {
+            TrayHandle::Tray(shown) => match &mut *shown.lock().await {
+                Shown::Visible(handle) => {
+                    let _ = handle.update(f).await;
+                }
+                Shown::Hidden(tray) => f(tray),
+            },
+            TrayHandle::Headless(tray) => {
+                let mut tray = tray.lock().unwrap();
+                let before = tray.title();
+                f(&mut tray);
+                let after = tray.title();
+                if after != before {
+                    println!("{after}");
+                }
+            }
+        }
+    }
+
+    async fn set_visible(&self, visible: bool) {
+        let TrayHandle::Tray(shown) = self else {
+            return;
+        };
+        let mut shown = shown.lock().await;
+        match &*shown {
+            Shown::Visible(handle) if !visible => {
+                let Some(tray) = handle.update(|tray| tray.clone()).await else {
+                    return;
+                };
+                handle.shutdown().await;
+                debug!("Hiding the tray icon until the device runs on battery");
+                *shown = Shown::Hidden(Box::new(tray));
+            }
+            Shown::Hidden(tray) if visible => match (**tray).clone().spawn().await {
+                Ok(handle) => {
+                    debug!("Device is on battery; showing the tray icon");
+                    *shown = Shown::Visible(handle);
+                }
+                Err(err) => warn!("Failed to show the tray icon again: {err}"),
+            },
+            _ => {}
+        }
+    }
+
+    async fn shutdown(&self) {
+        if let TrayHandle::Tray(shown) = self
+            && let Shown::Visible(handle) = &*shown.lock().await
+        {
+            handle.shutdown().await;
+        }
+    }
+}
+
+/// Aborts the wrapped task when dropped, so cancelling the supervisor also stops the poller.
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Everything the poll task needs, cloned into each run by the supervisor.
+#[derive(Clone)]
+struct PollContext {
+    manager: Arc<Manager>,
+    handle: TrayHandle,
+    batteries: BatteryCache,
+    refresh: Arc<Notify>,
+    /// The latest good config, replaced whenever the file is reloaded.
+    config: watch::Receiver<Config>,
+    #[cfg(feature = "lighting")]
+    effects: Option<SharedEffectStore>,
+}
+
+/// The parts of the config the poll loop works from, rebuilt whenever the file is reloaded.
+struct PollSettings {
+    battery: BatteryConfig,
+    /// The icon's low threshold, which polls speed up as a battery approaches.
+    low_threshold: u8,
+    title_format: Option<TitleTemplate>,
+    ignore_serials: HashSet<String>,
+    #[cfg(feature = "dpi")]
+    dpi_stages: HashMap<String, DpiStagesConfig>,
+    #[cfg(feature = "lighting")]
+    off_on_low_battery: HashSet<String>,
+    #[cfg(feature = "lighting")]
+    device_icon_color: bool,
+    #[cfg(feature = "temperature")]
+    temperature: TemperatureConfig,
+    #[cfg(feature = "reqwest")]
+    device_images: bool,
+}
+
+impl PollSettings {
+    fn new(config: &Config) -> Self {
+        Self {
+            battery: config.battery,
+            low_threshold: config.icon.low_threshold,
+            title_format: config.title_format.as_deref().map(TitleTemplate::parse),
+            ignore_serials: config.ignore_serials.clone(),
+            #[cfg(feature = "dpi")]
+            dpi_stages: config.dpi.clone(),
+            #[cfg(feature = "lighting")]
+            off_on_low_battery: config.lighting.off_on_low_battery.clone(),
+            #[cfg(feature = "lighting")]
+            device_icon_color: config.icon.icon_color == IconColor::Device,
+            #[cfg(feature = "temperature")]
+            temperature: config.temperature.clone(),
+            #[cfg(feature = "reqwest")]
+            device_images: config.icon.device_image,
+        }
+    }
+
+    fn interval(&self) -> AdaptiveInterval {
+        if self.battery.adaptive_polling {
+            AdaptiveInterval::new(
+                Duration::from_millis(self.battery.min_poll_ms),
+                Duration::from_millis(self.battery.max_poll_ms),
+            )
+        } else {
+            AdaptiveInterval::new(POLL_INTERVAL, POLL_INTERVAL)
+        }
+    }
+}
+
+/// Keeps the poll task alive, restarting it if it panics on an unexpected daemon reply.
+async fn supervise_poll_task(context: PollContext) {
+    loop {
+        let task = tokio::spawn(poll_devices(context.clone()));
+        let _abort = AbortOnDrop(task.abort_handle());
+        match task.await {
+            Ok(()) => return,
+            Err(err) => {
+                error!("Device poll task stopped unexpectedly: {err}; restarting");
+                tokio::time::sleep(Duration::from_secs(5)).await;
+            }
+        }
+    }
+}
+
+async fn poll_devices(context: PollContext) {
+    let PollContext {
+        manager,
+        handle,
+        batteries,
+        refresh,
+        mut config,
+        #[cfg(feature = "lighting")]
+        effects,
+    } = context;
+    let mut settings = PollSettings::new(&config.borrow_and_update());
+    // Cleared once the watcher stops, e.g. when there is no config directory to watch.
+    let mut watching_config = true;
+    let mut daemon_online = false;
+    let mut smoother = BatterySmoother::new(settings.battery.smoothing_window);
+    let mut charge_estimator = ChargeEstimator::default();
+    let mut visibility = VisibilityDebounce::default();
+    #[cfg(feature = "dpi")]
+    let mut applied_dpi_stages = HashSet::new();
+    #[cfg(feature = "lighting")]
+    let mut replayed_effects = HashSet::new();
+    #[cfg(feature = "lighting")]
+    let mut lights_off = HashSet::new();
+    #[cfg(feature = "temperature")]
+    let mut cpu_lighting = TemperatureLighting::new(settings.temperature.clone());
+    // Looked up once per serial; `None` records a device without a usable picture.
+    #[cfg(feature = "reqwest")]
+    let mut images: HashMap<String, Option<ksni::Icon>> = HashMap::new();
+    // Firmware doesn't change while the tray runs, so it is read once per device.
+    let mut firmware_versions: HashMap<String, Option<String>> = HashMap::new();
+    let mut interval = settings.interval();
+    let mut wait = Duration::ZERO;
+    loop {
+        // The loop body never overlaps itself; a refresh requested mid-poll leaves a permit
+        // behind so the next pass starts straight after this one.
+        let mut reloaded = false;
+        tokio::select! {
+            _ = tokio::time::sleep(wait) => {}
+            _ = refresh.notified() => {}
+            changed = config.changed(), if watching_config => {
+                watching_config = changed.is_ok();
+                reloaded = watching_config;
+            }
+        }
+        if reloaded {
+            let next = PollSettings::new(&config.borrow_and_update());
+            if next.battery.smoothing_window != settings.battery.smoothing_window {
+                smoother = BatterySmoother::new(next.battery.smoothing_window);
+            }
+            interval = next.interval();
+            // Forgetting what was applied lets edited stages reach mice that are already in.
+            #[cfg(feature = "dpi")]
+            applied_dpi_stages.clear();
+            #[cfg(feature = "temperature")]
+            {
+                cpu_lighting = TemperatureLighting::new(next.temperature.clone());
+            }
+            settings = next;
+        }
+        // Every read below works from this one listing, so each device is introspected once a
+        // tick. `None` means the daemon couldn't be reached.
+        let devices: Option<Vec<Arc<Device>>> = manager
+            .open_devices()
+            .await
+            .ok()
+            .map(|devices| devices.into_iter().map(Arc::new).collect());
+        let listed = devices.as_deref().unwrap_or_default();
+        let summaries = manager.summarize_batteries(listed.iter().map(Arc::as_ref)).await;
+        let summaries: Vec<_> = summaries
+            .into_iter()
+            .filter(|summary| !settings.ignore_serials.contains(&summary.serial))
+            .collect();
+        wait = interval.next(&summaries, settings.low_threshold);
+        let online = manager.is_daemon_running().await.unwrap_or(false);
+        if online != daemon_online {
+            daemon_online = online;
+            let version = if online {
+                manager.get_daemon_version().await.ok()
+            } else {
+                None
+            };
+            info!("OpenRazer daemon version: {}", version.as_deref().unwrap_or("unknown"));
+            handle.update(|tray| tray.daemon_version = version).await;
+        }
+        #[cfg(feature = "dpi")]
+        dpi::apply_configured_stages(listed, &settings.dpi_stages, &mut applied_dpi_stages).await;
+        #[cfg(feature = "lighting")]
+        if let Some(effects) = &effects {
+            effects::replay_saved_effects(listed, effects, &mut replayed_effects).await;
+        }
+        #[cfg(feature = "lighting")]
+        if !settings.off_on_low_battery.is_empty() {
+            let serials = &settings.off_on_low_battery;
+            battery_saver::apply(listed, &summaries, serials, &mut lights_off).await;
+        }
+        #[cfg(feature = "temperature")]
+        cpu_lighting.apply(listed).await;
+        let ignore_serials = &settings.ignore_serials;
+        let mut reading =
+            read_battery_state(&manager, devices.as_deref(), summaries, ignore_serials).await;
+        let mut charge_eta = None;
+        // Only the displayed level is smoothed; the ETA and presence cache use raw readings.
+        if let (IconState::Battery(percent), Some(device)) = (reading.state, &reading.device) {
+            let path = device.object_path().as_str();
+            charge_eta = charge_estimator.record(path, percent, reading.charging, Instant::now());
+            reading.state = IconState::Battery(smoother.smooth(path, percent));
+        }
+        #[cfg(feature = "lighting")]
+        let outline_dim = read_outline_dim(reading.device.as_deref()).await;
+        #[cfg(not(feature = "lighting"))]
+        let outline_dim = 1.0;
+        #[cfg(feature = "lighting")]
+        let device_fill = match &reading.device {
+            Some(device) if settings.device_icon_color => read_static_color(device).await,
+            _ => None,
+        };
+        #[cfg(feature = "reqwest")]
+        let device_image = match &reading.device {
+            Some(device) if settings.device_images => {
+                let serial = device.serial_from_path().unwrap_or_default();
+                if !images.contains_key(serial) {
+                    let image = device_image::tray_icon(device, serial).await;
+                    images.insert(serial.to_string(), image);
+                }
+                images[serial].clone()
+            }
+            _ => None,
+        };
+        let primary = reading.batteries.first();
+        let custom_title = match (&settings.title_format, reading.state, primary) {
+            (Some(template), IconState::Battery(percent), Some((name, _, _))) => {
+                Some(template.render(percent, name, reading.charging))
+            }
+            _ => None,
+        };
+        let firmware = match &reading.device {
+            Some(device) => {
+                let path = device.object_path().to_string();
+                if !firmware_versions.contains_key(&path) {
+                    let version = read_firmware(device).await;
+                    firmware_versions.insert(path.clone(), version);
+                }
+                firmware_versions[&path].clone()
+            }
+            None => None,
+        };
+        let low_battery_threshold = match &reading.device {
+            Some(device) if device.has_feature("low_battery_threshold") => {
+                device.get_low_battery_threshold().await.ok()
+            }
+            _ => None,
+        };
+        #[cfg(feature = "dpi")]
+        let device_dpi_stages = read_dpi_stages(reading.device.as_deref()).await;
+        #[cfg(feature = "dpi")]
+        let device_dpi = match (&reading.device, &reading.device_type) {
+            (Some(device), Some(DeviceType::Mouse)) if device.has_feature("dpi") => {
+                device.get_dpi().await.ok()
+            }
+            _ => None,
+        };
+        batteries.replace(reading.batteries.clone());
+        #[cfg(feature = "lighting")]
+        let keyboards = keyboards(listed).await;
+        #[cfg(feature = "lighting")]
+        let keyboard = read_game_mode(&keyboards).await;
+        #[cfg(feature = "lighting")]
+        let profile_leds = read_profile_leds(&keyboards).await;
+        let on_battery = matches!(reading.state, IconState::Battery(_)) && !reading.charging;
+        handle
+            .update(|tray| {
+                tray.state = reading.state;
+                tray.charging = reading.charging;
+                tray.animation_phase = match tray.charging_sweep() {
+                    Some(phase) => phase.wrapping_add(1),
+                    None => 0,
+                };
+                tray.charge_eta = charge_eta;
+                tray.battery_cells = reading.cells;
+                tray.custom_title = custom_title;
+                tray.outline_dim = outline_dim;
+                #[cfg(feature = "lighting")]
+                {
+                    tray.device_fill = device_fill;
+                }
+                tray.device_type = reading.device_type;
+                tray.firmware = firmware;
+                tray.low_battery_threshold = low_battery_threshold;
+                #[cfg(feature = "reqwest")]
+                {
+                    tray.device_image = device_image;
+                }
+                tray.device = reading.device;
+                #[cfg(feature = "dpi")]
+                {
+                    tray.dpi_stages = device_dpi_stages;
+                    tray.dpi = device_dpi;
+                }
+                #[cfg(feature = "lighting")]
+                {
+                    match keyboard {
+                        Some((device, game_mode)) => {
+                            tray.keyboard = Some(device);
+                            tray.game_mode = game_mode;
+                        }
+                        None => tray.keyboard = None,
+                    }
+                    match profile_leds {
+                        Some((device, leds)) => {
+                            tray.profile_led_keyboard = Some(device);
+                            tray.profile_leds = leds;
+                        }
+                        None => {
+                            tray.profile_led_keyboard = None;
+                            tray.profile_leds = Vec::new();
+                        }
+                    }
+                }
+            })
+            .await;
+        // Applied after the update, so a tray that reappears shows the fresh reading.
+        if settings.battery.only_on_battery {
+            handle.set_visible(visibility.update(on_battery, Instant::now())).await;
+        }
+    }
 }
 
 #[cfg(unix)]
-fn daemonize_background() -> io::Result<()> {
-    if env::var_os("RUSTY_RAZER_TRAY_FOREGROUND").is_some() {
+fn daemonize_background(foreground: bool) -> io::Result<()> {
+    if foreground || env::var_os("RUSTY_RAZER_TRAY_FOREGROUND").is_some() {
         return Ok(());
     }
 
@@ -133,104 +1418,240 @@ fn daemonize_background() -> io::Result<()> {
 }
 
 #[cfg(not(unix))]
-fn daemonize_background() -> io::Result<()> {
+fn daemonize_background(_foreground: bool) -> io::Result<()> {
     Ok(())
 }
 
-fn acquire_single_instance_lock() -> std::io::Result<std::fs::File> {
-    let runtime_dir = env::var("XDG_RUNTIME_DIR").unwrap_or_else(|_| "/tmp".to_string());
-    let lock_path = format!("{}/rusty-razer-tray.lock", runtime_dir);
-    let file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(lock_path)?;
-    file.try_lock_exclusive()?;
-    Ok(file)
+struct BatteryReading {
+    state: IconState,
+    charging: bool,
+    device_type: Option<DeviceType>,
+    device: Option<Arc<Device>>,
+    batteries: Vec<presence::BatteryEntry>,
+    /// `BatterySummary::cells_text` of the primary device.
+    cells: Option<String>,
 }
 
-async fn read_battery_percent(manager: &Manager) -> Option<u8> {
-    let devices = manager.get_devices().await.ok()?;
-    for path in devices {
-        let device = match manager.get_device(path).await {
-            Ok(device) => device,
-            Err(_) => continue,
-        };
-        if !device.has_feature("battery") {
-            continue;
+impl BatteryReading {
+    fn without_battery(state: IconState) -> Self {
+        Self {
+            state,
+            charging: false,
+            device_type: None,
+            device: None,
+            batteries: Vec::new(),
+            cells: None,
         }
-        if let Ok(percent) = device.get_battery_percent().await {
-            let percent = percent.round().clamp(0.0, 100.0) as u8;
-            return Some(percent);
+    }
+}
+
+/// Turns one battery summary into the tray state; the icon reflects the first battery found.
+/// `devices` is this tick's listing, `None` when the daemon couldn't be reached.
+async fn read_battery_state(
+    manager: &Manager,
+    devices: Option<&[Arc<Device>]>,
+    summaries: Vec<BatterySummary>,
+    ignore_serials: &HashSet<String>,
+) -> BatteryReading {
+    let devices = match devices {
+        Some(devices) => Some(responsive_devices(devices, &summaries, ignore_serials).await),
+        None => None,
+    };
+    // An unreachable daemon counts as every device going away.
+    let paths: Vec<OwnedObjectPath> = devices
+        .iter()
+        .flatten()
+        .map(|device| device.object_path().clone())
+        .collect();
+    manager.publish_devices(&paths);
+    let Some(devices) = devices else {
+        manager.publish_batteries(&[]);
+        return BatteryReading::without_battery(IconState::Disconnected);
+    };
+    let Some(first) = devices.first().cloned() else {
+        manager.publish_batteries(&[]);
+        return BatteryReading::without_battery(IconState::NoDevice);
+    };
+    manager.publish_batteries(&summaries);
+    let mut reading = BatteryReading::without_battery(IconState::Connected);
+    reading.batteries = summaries
+        .iter()
+        .map(|summary| (summary.name.clone(), summary.percent, summary.charging))
+        .collect();
+    match summaries.into_iter().next() {
+        Some(primary) => {
+            reading.state = IconState::Battery(primary.percent);
+            reading.charging = primary.charging;
+            reading.cells = primary.cells_text();
+            reading.device = devices
+                .iter()
+                .find(|device| device.serial_from_path() == Some(primary.serial.as_str()))
+                .cloned();
+            reading.device_type = Some(primary.device_type);
+            // A mouse that is off reads as an empty battery; show it as unreachable instead.
+            if let Some(device) = &reading.device
+                && !device.dongle_connected().await.unwrap_or(true)
+            {
+                reading.state = IconState::OutOfRange;
+            }
         }
+        None => reading.device = Some(first),
     }
-    None
+    reading
 }
 
-fn render_digit_icon(value: u8) -> ksni::Icon {
-    let width = 16u32;
-    let height = 16u32;
-    let mut data = vec![0u8; (width * height * 4) as usize];
+/// Drops ignored devices, and ones the daemon still lists but that no longer answer, e.g. after
+/// an unplug. Devices that just reported a battery are known to be there and aren't pinged.
+async fn responsive_devices(
+    devices: &[Arc<Device>],
+    summaries: &[BatterySummary],
+    ignore_serials: &HashSet<String>,
+) -> Vec<Arc<Device>> {
+    let mut responsive = Vec::new();
+    for device in devices {
+        let serial = device.serial_from_path().unwrap_or_default();
+        if ignore_serials.contains(serial) {
+            continue;
+        }
+        if !summaries.iter().any(|summary| summary.serial == serial)
+            && !device.is_responsive().await
+        {
+            debug!("Skipping {serial}, which the daemon lists but doesn't answer");
+            continue;
+        }
+        responsive.push(device.clone());
+    }
+    responsive
+}
 
-    let outline = (255u8, 220u8, 220u8, 220u8);
-    let fill = if value <= 25 {
-        (255u8, 220u8, 60u8, 60u8)
-    } else if value <= 50 {
-        (255u8, 255u8, 224u8, 0u8)
-    } else {
-        (255u8, 0u8, 255u8, 0u8)
+/// Maps the device's overall brightness to an outline dim factor. Even fully off keeps the
+/// outline visible, and devices without a brightness control always get the full outline.
+#[cfg(feature = "lighting")]
+async fn read_outline_dim(device: Option<&Device>) -> f32 {
+    const MIN_OUTLINE_DIM: f32 = 0.4;
+    let Some(device) = device.filter(|device| device.supports_brightness(LedId::Unspecified))
+    else {
+        return 1.0;
     };
+    match device.get_brightness(LedId::Unspecified).await {
+        Ok(brightness) => {
+            let level = (brightness as f32 / 100.0).clamp(0.0, 1.0);
+            MIN_OUTLINE_DIM + (1.0 - MIN_OUTLINE_DIM) * level
+        }
+        Err(_) => 1.0,
+    }
+}
 
-    let mut set_px = |x: u32, y: u32, color: (u8, u8, u8, u8)| {
-        if x >= width || y >= height {
-            return;
+/// The colour of the first LED running a static effect, as an opaque icon colour.
+#[cfg(feature = "lighting")]
+async fn read_static_color(device: &Device) -> Option<Colour> {
+    let mut leds: Vec<LedId> = device.supported_leds().keys().copied().collect();
+    leds.sort_by_key(|led| *led != LedId::Unspecified);
+    for led in leds {
+        if !matches!(device.get_led_effect(led).await, Ok(LedEffect::Static)) {
+            continue;
         }
-        let idx = ((y * width + x) * 4) as usize;
-        data[idx] = color.0;
-        data[idx + 1] = color.1;
-        data[idx + 2] = color.2;
-        data[idx + 3] = color.3;
-    };
+        if let Ok(Rgb { r, g, b }) = device.get_static_color(led).await {
+            return Some((255, r, g, b));
+        }
+    }
+    None
+}
+
+async fn read_firmware(device: &Device) -> Option<String> {
+    if !device.capabilities().contains("razer.device.misc;getFirmware") {
+        return None;
+    }
+    device.get_firmware_version().await.ok()
+}
 
-    draw_outlined_rect(1, 4, 12, 12, outline, &mut set_px);
-    draw_outlined_rect(13, 7, 14, 9, outline, &mut set_px);
+#[cfg(feature = "dpi")]
+async fn read_dpi_stages(device: Option<&Device>) -> Option<(u8, Vec<Dpi>)> {
+    let device = device.filter(|device| device.has_feature("dpi_stages"))?;
+    device.get_dpi_stages().await.ok()
+}
 
-    // Battery fill (inner area).
-    let inner_width = 10u32;
-    let filled = (value.min(100) as u32 * inner_width) / 100;
-    if filled > 0 {
-        for x in 2..=1 + filled {
-            for y in 5..=11 {
-                set_px(x, y, fill);
-            }
+/// The keyboards among this tick's devices.
+#[cfg(feature = "lighting")]
+async fn keyboards(devices: &[Arc<Device>]) -> Vec<Arc<Device>> {
+    let mut keyboards = Vec::new();
+    for device in devices {
+        if let Ok(device_type) = device.get_device_type().await
+            && DeviceType::from(device_type.as_str()) == DeviceType::Keyboard
+        {
+            keyboards.push(device.clone());
         }
     }
+    keyboards
+}
 
-    ksni::Icon {
-        width: width as i32,
-        height: height as i32,
-        data,
+/// The first keyboard with game mode, and its game mode.
+#[cfg(feature = "lighting")]
+async fn read_game_mode(keyboards: &[Arc<Device>]) -> Option<(Arc<Device>, bool)> {
+    for device in keyboards {
+        if !device.has_feature("game_mode") {
+            continue;
+        }
+        if let Ok(game_mode) = device.get_game_mode().await {
+            return Some((device.clone(), game_mode));
+        }
     }
+    None
 }
 
-fn draw_outlined_rect(
-    x0: u32,
-    y0: u32,
-    x1: u32,
-    y1: u32,
-    colour: (u8, u8, u8, u8),
-    mut set_function: impl FnMut(u32, u32, (u8, u8, u8, u8)),
-) {
-    let (min_x, max_x) = if x0 <= x1 { (x0, x1) } else { (x1, x0) };
-    let (min_y, max_y) = if y0 <= y1 { (y0, y1) } else { (y1, y0) };
+/// The first keyboard with any profile LEDs, and the state of each one it has. Every LED is
+/// checked on its own, since `get_profile_led` needs that LED's `get<Colour>LED` method.
+#[cfg(feature = "lighting")]
+async fn read_profile_leds(
+    keyboards: &[Arc<Device>],
+) -> Option<(Arc<Device>, Vec<(LedId, bool)>)> {
+    for device in keyboards {
+        let mut leds = Vec::new();
+        for led in [LedId::KeymapRedLED, LedId::KeymapGreenLED, LedId::KeymapBlueLED] {
+            if let Ok(on) = device.get_profile_led(led).await {
+                leds.push((led, on));
+            }
+        }
+        if !leds.is_empty() {
+            return Some((device.clone(), leds));
+        }
+    }
+    None
+}
 
-    for x in min_x..=max_x {
-        set_function(x, min_y, colour);
-        set_function(x, max_y, colour);
+/// Picks the closest standard symbolic battery icon, in steps of ten like Adwaita ships them.
+fn symbolic_icon_name(state: IconState, charging: bool) -> String {
+    match state {
+        IconState::Battery(percent) => {
+            let level = (u32::from(percent.min(100)) + 5) / 10 * 10;
+            match (level, charging) {
+                (100, true) => "battery-level-100-charged-symbolic".to_string(),
+                (level, true) => format!("battery-level-{level}-charging-symbolic"),
+                (level, false) => format!("battery-level-{level}-symbolic"),
+            }
+        }
+        IconState::Connected => "input-gaming-symbolic".to_string(),
+        IconState::NoDevice | IconState::OutOfRange | IconState::Disconnected => {
+            "battery-missing-symbolic".to_string()
+        }
     }
+}
 
-    for y in min_y..=max_y {
-        set_function(min_x, y, colour);
-        set_function(max_x, y, colour);
+fn render_icon(
+    state: IconState,
+    theme: &IconTheme,
+    outline_dim: f32,
+    fill: Option<Colour>,
+    sweep: Option<u32>,
+) -> ksni::Icon {
+    match state {
+        IconState::Battery(value) => {
+            icons::battery_glyph(value, theme, outline_dim, fill, sweep, icons::DEFAULT_SIZE)
+        }
+        IconState::Connected => icons::connected_glyph(theme, icons::DEFAULT_SIZE),
+        IconState::NoDevice => icons::no_device_glyph(theme, icons::DEFAULT_SIZE),
+        IconState::OutOfRange | IconState::Disconnected => {
+            icons::disconnected_glyph(theme, icons::DEFAULT_SIZE)
+        }
     }
 }
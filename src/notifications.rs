@@ -0,0 +1,138 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::process::{Command, Stdio};
+use std::sync::{Arc, OnceLock};
+
+use log::warn;
+use serde::Deserialize;
+use zbus::zvariant::Value;
+use zbus::{Connection, Proxy};
+
+/// How notifications reach the user, as `[notifications] backend = "..."` in the config.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(tag = "backend", rename_all = "kebab-case")]
+pub enum NotifierKind {
+    /// `org.freedesktop.Notifications` on the session bus.
+    #[default]
+    Dbus,
+    /// Runs `notify-send`, for desktops whose notification daemon only works through libnotify.
+    NotifySend,
+    /// Writes one tab-separated `icon summary body` line per notification to `path`, for a
+    /// status bar or script to pick up.
+    Fifo { path: PathBuf },
+}
+
+type SendFuture<'a> = Pin<Box<dyn Future<Output = io::Result<()>> + Send + 'a>>;
+
+pub trait Notifier: Send + Sync {
+    fn send<'a>(&'a self, icon: &'a str, summary: &'a str, body: &'a str) -> SendFuture<'a>;
+}
+
+pub struct DbusNotifier;
+
+impl Notifier for DbusNotifier {
+    fn send<'a>(&'a self, icon: &'a str, summary: &'a str, body: &'a str) -> SendFuture<'a> {
+        Box::pin(async move { notify_dbus(icon, summary, body).await.map_err(io::Error::other) })
+    }
+}
+
+async fn notify_dbus(icon: &str, summary: &str, body: &str) -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    let proxy = Proxy::new(
+        &connection,
+        "org.freedesktop.Notifications",
+        "/org/freedesktop/Notifications",
+        "org.freedesktop.Notifications",
+    )
+    .await?;
+    let actions: Vec<&str> = Vec::new();
+    let hints: HashMap<&str, Value<'_>> = HashMap::new();
+    let _id: u32 = proxy
+        .call(
+            "Notify",
+            &(env!("CARGO_PKG_NAME"), 0u32, icon, summary, body, actions, hints, -1i32),
+        )
+        .await?;
+    Ok(())
+}
+
+pub struct NotifySendNotifier;
+
+impl Notifier for NotifySendNotifier {
+    fn send<'a>(&'a self, icon: &'a str, summary: &'a str, body: &'a str) -> SendFuture<'a> {
+        let args = [
+            "--app-name".to_string(),
+            env!("CARGO_PKG_NAME").to_string(),
+            "--icon".to_string(),
+            icon.to_string(),
+            summary.to_string(),
+            body.to_string(),
+        ];
+        Box::pin(blocking(move || {
+            let status = Command::new("notify-send")
+                .args(args)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map_err(|err| io::Error::new(err.kind(), format!("notify-send: {err}")))?;
+            if status.success() {
+                Ok(())
+            } else {
+                Err(io::Error::other(format!("notify-send exited with {status}")))
+            }
+        }))
+    }
+}
+
+pub struct FifoNotifier {
+    path: Arc<PathBuf>,
+}
+
+impl Notifier for FifoNotifier {
+    fn send<'a>(&'a self, icon: &'a str, summary: &'a str, body: &'a str) -> SendFuture<'a> {
+        // Tabs and newlines would break the one-line-per-notification format.
+        let line = [icon, summary, body]
+            .map(|field| field.replace(['\t', '\n'], " "))
+            .join("\t");
+        let path = self.path.clone();
+        // Opening a FIFO blocks until something reads it, so this stays off the runtime.
+        Box::pin(blocking(move || {
+            let mut file = OpenOptions::new().append(true).create(true).open(&*path)?;
+            writeln!(file, "{line}")
+        }))
+    }
+}
+
+async fn blocking(work: impl FnOnce() -> io::Result<()> + Send + 'static) -> io::Result<()> {
+    tokio::task::spawn_blocking(work).await.map_err(io::Error::other)?
+}
+
+pub fn backend(kind: &NotifierKind) -> Box<dyn Notifier> {
+    match kind {
+        NotifierKind::Dbus => Box::new(DbusNotifier),
+        NotifierKind::NotifySend => Box::new(NotifySendNotifier),
+        NotifierKind::Fifo { path } => Box::new(FifoNotifier { path: Arc::new(path.clone()) }),
+    }
+}
+
+static NOTIFIER: OnceLock<Box<dyn Notifier>> = OnceLock::new();
+
+/// Picks the backend `send` uses. Only the first call has an effect; until then notifications
+/// go over D-Bus.
+pub fn init(kind: &NotifierKind) {
+    let _ = NOTIFIER.set(backend(kind));
+}
+
+/// Shows a desktop notification through the configured backend, with `icon` as a freedesktop
+/// icon name. Failures are logged rather than returned, since a missing notification daemon
+/// shouldn't stop anything else.
+pub async fn send(icon: &str, summary: &str, body: &str) {
+    let notifier = NOTIFIER.get_or_init(|| Box::new(DbusNotifier));
+    if let Err(err) = notifier.send(icon, summary, body).await {
+        warn!("Failed to show notification {summary:?}: {err}");
+    }
+}
@@ -1,35 +1,141 @@
-use std::collections::{HashMap, HashSet};
+#[cfg(any(feature = "battery", feature = "lighting"))]
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::future::Future;
+#[cfg(feature = "battery")]
+use std::sync::{Arc, Mutex};
+#[cfg(feature = "lighting")]
+use std::sync::OnceLock;
+use std::time::Duration;
+#[cfg(feature = "battery")]
+use std::time::Instant;
 
 use roxmltree::Document;
+use serde::Serialize;
 use serde_json::Value;
-use zbus::zvariant::OwnedObjectPath;
+#[cfg(feature = "lighting")]
+use tokio::sync::OnceCell;
+use zbus::zvariant::{DynamicType, OwnedObjectPath, OwnedValue, Structure};
 use zbus::{Connection, Proxy};
 
-use crate::openrazer::types::{Dpi, LedId, MatrixDimensions, Rgb};
-use crate::openrazer::OPENRAZER_SERVICE_NAME;
+use crate::openrazer::error::RazerError;
+#[cfg(feature = "lighting")]
+use crate::openrazer::keymap;
+use crate::openrazer::retry::retry_call;
+use crate::openrazer::timeout::{with_timeout, DEFAULT_CALL_TIMEOUT};
+#[cfg(feature = "battery")]
+use crate::openrazer::types::BatteryCell;
+#[cfg(feature = "dpi")]
+use crate::openrazer::types::Dpi;
+#[cfg(feature = "lighting")]
+use crate::openrazer::types::{Frame, LedEffect, LedId, MatrixDimensions, Rgb, SoftwareEffect};
+use crate::openrazer::{OPENRAZER_ROOT_PATH, OPENRAZER_SERVICE_NAME};
+
+/// How long `is_responsive` waits; a device that is really there answers well within this.
+pub const PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// How long a battery reading is reused before asking the daemon again.
+#[cfg(feature = "battery")]
+pub const DEFAULT_BATTERY_CACHE_TTL: Duration = Duration::from_millis(500);
+
+/// Stage limit assumed when the daemon can't say, matching the five stages most Razer mice have.
+#[cfg(feature = "dpi")]
+pub const DEFAULT_MAX_DPI_STAGES: u8 = 5;
+
+/// What `getDeviceMode` reports while the host is drawing the lighting, as `mode:param`.
+#[cfg(feature = "lighting")]
+const DRIVER_DEVICE_MODE: &str = "3:0";
+
+/// How far a DPI read-back may be from the requested value, since many sensors only step in
+/// increments of 50.
+#[cfg(feature = "dpi")]
+pub const DPI_TOLERANCE: u16 = 50;
 
 pub struct Device {
     connection: Connection,
     object_path: OwnedObjectPath,
     introspection: HashSet<String>,
     supported_features: HashSet<String>,
+    call_timeout: Duration,
+    #[cfg(feature = "lighting")]
     supported_leds: HashMap<LedId, String>,
+    // The matrix size is fixed per device, so it is only fetched once.
+    #[cfg(feature = "lighting")]
+    matrix_dimensions: OnceCell<MatrixDimensions>,
+    // Filled in by `get_keyboard_layout` so `key_position` can stay synchronous.
+    #[cfg(feature = "lighting")]
+    keyboard_layout: OnceLock<String>,
+    // Lets the tray, tooltip and presence interface share one read instead of each asking.
+    #[cfg(feature = "battery")]
+    battery_cache: SharedBatteryCache,
+    #[cfg(feature = "battery")]
+    battery_cache_ttl: Duration,
+}
+
+/// Battery readings by object path. A `Manager` hands the same map to every `Device` it opens,
+/// so separate handles to one device reuse each other's reads.
+#[cfg(feature = "battery")]
+pub(crate) type SharedBatteryCache = Arc<Mutex<HashMap<String, (Instant, f64)>>>;
+
+/// The serial at the end of a device's object path. The daemon names each device
+/// `/org/razer/device/<serial>`, so this saves asking over D-Bus. `None` for other paths.
+pub fn serial_from_path(path: &str) -> Option<&str> {
+    let serial = path.strip_prefix(OPENRAZER_ROOT_PATH)?.strip_prefix("/device/")?;
+    (!serial.is_empty() && !serial.contains('/')).then_some(serial)
 }
 
 impl Device {
-    pub async fn new(connection: Connection, object_path: OwnedObjectPath) -> zbus::Result<Self> {
-        let introspection = Self::introspect(&connection, &object_path).await?;
+    pub async fn new(
+        connection: Connection,
+        object_path: OwnedObjectPath,
+    ) -> Result<Self, RazerError> {
+        Self::new_with_timeout(connection, object_path, DEFAULT_CALL_TIMEOUT).await
+    }
+
+    /// Like `new`, but every D-Bus call, including the introspection here, gives up after
+    /// `call_timeout`.
+    pub async fn new_with_timeout(
+        connection: Connection,
+        object_path: OwnedObjectPath,
+        call_timeout: Duration,
+    ) -> Result<Self, RazerError> {
+        let introspection = Self::introspect(&connection, &object_path, call_timeout).await?;
         let mut device = Self {
             connection,
             object_path,
             introspection,
             supported_features: HashSet::new(),
+            call_timeout,
+            #[cfg(feature = "lighting")]
             supported_leds: HashMap::new(),
+            #[cfg(feature = "lighting")]
+            matrix_dimensions: OnceCell::new(),
+            #[cfg(feature = "lighting")]
+            keyboard_layout: OnceLock::new(),
+            #[cfg(feature = "battery")]
+            battery_cache: SharedBatteryCache::default(),
+            #[cfg(feature = "battery")]
+            battery_cache_ttl: DEFAULT_BATTERY_CACHE_TTL,
         };
         device.setup_capabilities();
         Ok(device)
     }
 
+    /// Re-introspects the device and drops cached metadata, e.g. after a firmware mode switch.
+    pub async fn refresh_metadata(&mut self) -> Result<(), RazerError> {
+        self.introspection =
+            Self::introspect(&self.connection, &self.object_path, self.call_timeout).await?;
+        self.supported_features.clear();
+        #[cfg(feature = "lighting")]
+        {
+            self.supported_leds.clear();
+            self.matrix_dimensions.take();
+            self.keyboard_layout.take();
+        }
+        self.setup_capabilities();
+        Ok(())
+    }
+
     pub fn object_path(&self) -> &OwnedObjectPath {
         &self.object_path
     }
@@ -38,15 +144,47 @@ impl Device {
         self.supported_features.contains(feature)
     }
 
+    /// The features this crate recognised in the introspection, sorted: the names `has_feature`
+    /// accepts, such as `battery` or `dpi_stages`. See `capabilities` for the raw interface list.
+    pub fn features(&self) -> Vec<String> {
+        let mut features: Vec<String> = self.supported_features.iter().cloned().collect();
+        features.sort();
+        features
+    }
+
+    #[cfg(feature = "lighting")]
     pub fn supported_leds(&self) -> &HashMap<LedId, String> {
         &self.supported_leds
     }
 
-    pub async fn get_device_image_url(&self) -> zbus::Result<String> {
+    /// Every interface name and `interface;method` pair the device advertised when introspected.
+    pub fn capabilities(&self) -> &HashSet<String> {
+        &self.introspection
+    }
+
+    /// The Introspect XML exactly as the daemon sends it, doctype and all, for bug reports about
+    /// capability detection. It is fetched again on every call rather than kept around.
+    pub async fn raw_introspection(&self) -> Result<String, RazerError> {
+        Self::introspection_xml(&self.connection, &self.object_path, self.call_timeout).await
+    }
+
+    pub fn supported_methods(&self, interface: &str) -> Vec<String> {
+        let prefix = format!("{interface};");
+        let mut methods: Vec<String> = self
+            .introspection
+            .iter()
+            .filter_map(|entry| entry.strip_prefix(&prefix))
+            .map(str::to_string)
+            .collect();
+        methods.sort();
+        methods
+    }
+
+    pub async fn get_device_image_url(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        let payload: String = proxy.call("getRazerUrls", &()).await?;
+        let payload: String = self.timed(proxy.call("getRazerUrls", &())).await?;
         let value: Value = serde_json::from_str(&payload)
-            .map_err(|err| zbus::Error::Failure(err.to_string()))?;
+            .map_err(|err| RazerError::Parse(err.to_string()))?;
         Ok(value
             .get("top_img")
             .and_then(|item| item.as_str())
@@ -54,24 +192,52 @@ impl Device {
             .to_string())
     }
 
-    pub async fn get_device_mode(&self) -> zbus::Result<String> {
+    pub async fn get_device_mode(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        proxy.call("getDeviceMode", &()).await
+        self.timed(proxy.call("getDeviceMode", &())).await
+    }
+
+    pub fn serial_from_path(&self) -> Option<&str> {
+        serial_from_path(self.object_path.as_str())
     }
 
-    pub async fn get_serial(&self) -> zbus::Result<String> {
+    /// Taken from the object path when it has one, without a D-Bus call. `is_responsive` checks
+    /// it against the daemon's answer whenever it runs.
+    pub async fn get_serial(&self) -> Result<String, RazerError> {
+        if let Some(serial) = self.serial_from_path() {
+            return Ok(serial.to_string());
+        }
         let proxy = self.device_misc_proxy().await?;
-        proxy.call("getSerial", &()).await
+        self.timed(proxy.call("getSerial", &())).await
+    }
+
+    /// A quick `getSerial` round trip, to tell a device that is really there from one the daemon
+    /// still lists after it was unplugged. Errors and timeouts count as unresponsive.
+    pub async fn is_responsive(&self) -> bool {
+        let Ok(proxy) = self.device_misc_proxy().await else {
+            return false;
+        };
+        let limit = PING_TIMEOUT.min(self.call_timeout);
+        let Ok(serial) = with_timeout(limit, proxy.call::<_, _, String>("getSerial", &())).await
+        else {
+            return false;
+        };
+        if let Some(from_path) = self.serial_from_path()
+            && from_path != serial
+        {
+            log::warn!("Device {from_path} reports serial {serial}, not the one in its path");
+        }
+        true
     }
 
-    pub async fn get_device_name(&self) -> zbus::Result<String> {
+    pub async fn get_device_name(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        proxy.call("getDeviceName", &()).await
+        self.timed(proxy.call("getDeviceName", &())).await
     }
 
-    pub async fn get_device_type(&self) -> zbus::Result<String> {
+    pub async fn get_device_type(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        let device_type: String = proxy.call("getDeviceType", &()).await?;
+        let device_type: String = self.timed(proxy.call("getDeviceType", &())).await?;
         let mapped = match device_type.as_str() {
             "core" => "accessory",
             "mousemat" => "mousepad",
@@ -81,14 +247,14 @@ impl Device {
         Ok(mapped.to_string())
     }
 
-    pub async fn get_firmware_version(&self) -> zbus::Result<String> {
+    pub async fn get_firmware_version(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        proxy.call("getFirmware", &()).await
+        self.timed(proxy.call("getFirmware", &())).await
     }
 
-    pub async fn get_keyboard_layout(&self) -> zbus::Result<String> {
+    pub async fn get_keyboard_layout(&self) -> Result<String, RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        let layout: String = proxy.call("getKeyboardLayout", &()).await?;
+        let layout: String = self.timed(proxy.call("getKeyboardLayout", &())).await?;
         let mapped = match layout.as_str() {
             "de_DE" => "German",
             "el_GR" => "Greek",
@@ -102,162 +268,691 @@ impl Device {
             "pt_PT" => "Portuguese",
             _ => layout.as_str(),
         };
+        #[cfg(feature = "lighting")]
+        let _ = self.keyboard_layout.set(mapped.to_string());
         Ok(mapped.to_string())
     }
 
-    pub async fn get_poll_rate(&self) -> zbus::Result<u16> {
+    /// Whether the device runs off a battery over a wireless link. Daemons that report the
+    /// connection type are trusted; otherwise a device is taken as wireless when it has a battery.
+    pub async fn is_wireless(&self) -> Result<bool, RazerError> {
+        if !self.has_capability_internal("razer.device.misc", Some("getConnectionType")) {
+            return Ok(self.has_feature("battery"));
+        }
+        let proxy = self.device_misc_proxy().await?;
+        let connection: String = self.timed(proxy.call("getConnectionType", &())).await?;
+        Ok(!matches!(connection.to_ascii_lowercase().as_str(), "wired" | "usb"))
+    }
+
+    /// Whether the wireless link to the device is up, so a mouse that is off or out of range can
+    /// be told apart from a flat battery. Daemons without `getWirelessStatus` don't say, so a
+    /// reading of exactly 0% while not charging, which is what the dongle reports for a missing
+    /// mouse, is taken as disconnected.
+    #[cfg(feature = "battery")]
+    pub async fn dongle_connected(&self) -> Result<bool, RazerError> {
+        if self.has_capability_internal("razer.device.misc", Some("getWirelessStatus")) {
+            let proxy = self.device_misc_proxy().await?;
+            return self.timed(proxy.call("getWirelessStatus", &())).await;
+        }
+        let percent = self.get_battery_percent().await?;
+        Ok(percent > 0.0 || self.is_charging().await?)
+    }
+
+    /// Calls an arbitrary `razer.device.misc` method and returns the raw reply arguments, for
+    /// probing methods that don't have a typed wrapper yet.
+    pub async fn call_misc_method<B>(
+        &self,
+        method: &str,
+        args: &B,
+    ) -> Result<Vec<OwnedValue>, RazerError>
+    where
+        B: Serialize + DynamicType,
+    {
         let proxy = self.device_misc_proxy().await?;
-        let value: i32 = proxy.call("getPollRate", &()).await?;
+        let reply = self.timed(proxy.call_method(method, args)).await?;
+        let body = reply.body();
+        if body.is_empty() {
+            return Ok(Vec::new());
+        }
+        let fields: Structure = body.deserialize()?;
+        fields
+            .into_fields()
+            .into_iter()
+            .map(|field| OwnedValue::try_from(field).map_err(RazerError::from))
+            .collect()
+    }
+
+    pub async fn get_poll_rate(&self) -> Result<u16, RazerError> {
+        let proxy = self.device_misc_proxy().await?;
+        let value: i32 = self.timed(retry_call(|| proxy.call("getPollRate", &()))).await?;
         Ok(value as u16)
     }
 
-    pub async fn set_poll_rate(&self, poll_rate: u16) -> zbus::Result<()> {
+    pub async fn set_poll_rate(&self, poll_rate: u16) -> Result<(), RazerError> {
         let proxy = self.device_misc_proxy().await?;
-        proxy.call::<_, _, ()>("setPollRate", &(poll_rate)).await?;
+        self.timed(proxy.call::<_, _, ()>("setPollRate", &(poll_rate))).await?;
         Ok(())
     }
 
-    pub async fn get_supported_poll_rates(&self) -> zbus::Result<Vec<u16>> {
+    pub async fn get_supported_poll_rates(&self) -> Result<Vec<u16>, RazerError> {
         if !self.has_capability_internal("razer.device.misc", Some("getSupportedPollRates")) {
             return Ok(vec![125, 500, 1000]);
         }
         let proxy = self.device_misc_proxy().await?;
-        let values: Vec<i32> = proxy.call("getSupportedPollRates", &()).await?;
+        let values: Vec<i32> = self.timed(proxy.call("getSupportedPollRates", &())).await?;
         Ok(values.into_iter().map(|value| value as u16).collect())
     }
 
-    pub async fn set_dpi(&self, dpi: Dpi) -> zbus::Result<()> {
+    #[cfg(feature = "dpi")]
+    pub async fn set_dpi(&self, dpi: Dpi) -> Result<(), RazerError> {
         let proxy = self.device_dpi_proxy().await?;
-        proxy.call::<_, _, ()>("setDPI", &(dpi.dpi_x, dpi.dpi_y)).await?;
+        self.timed(proxy.call::<_, _, ()>("setDPI", &(dpi.dpi_x, dpi.dpi_y))).await?;
         Ok(())
     }
 
-    pub async fn get_dpi(&self) -> zbus::Result<Dpi> {
+    /// Sets the DPI and reads it back, failing with `DpiMismatch` if the device applied
+    /// something more than `DPI_TOLERANCE` away. Returns what the device actually reports.
+    #[cfg(feature = "dpi")]
+    pub async fn set_dpi_verified(&self, dpi: Dpi) -> Result<Dpi, RazerError> {
+        self.set_dpi(dpi).await?;
+        self.verify_dpi(dpi).await
+    }
+
+    /// Reads the DPI back and checks it against `dpi` like `set_dpi_verified`, for changes made
+    /// some other way, such as selecting a DPI stage.
+    #[cfg(feature = "dpi")]
+    pub async fn verify_dpi(&self, dpi: Dpi) -> Result<Dpi, RazerError> {
+        let applied = self.get_dpi().await?;
+        // Single-axis devices read back a Y of 0, which says nothing about what was applied.
+        let y_matches = applied.dpi_y == 0 || applied.dpi_y.abs_diff(dpi.dpi_y) <= DPI_TOLERANCE;
+        if applied.dpi_x.abs_diff(dpi.dpi_x) <= DPI_TOLERANCE && y_matches {
+            return Ok(applied);
+        }
+        log::debug!(
+            "{} applied DPI {applied:?} after being asked for {dpi:?}",
+            self.object_path.as_str()
+        );
+        Err(RazerError::DpiMismatch {
+            requested: dpi,
+            applied,
+        })
+    }
+
+    #[cfg(feature = "dpi")]
+    pub async fn get_dpi(&self) -> Result<Dpi, RazerError> {
         let proxy = self.device_dpi_proxy().await?;
-        let dpi: Vec<i32> = proxy.call("getDPI", &()).await?;
-        match dpi.len() {
-            1 => Ok(Dpi {
-                dpi_x: dpi[0] as u16,
+        let dpi: Vec<i32> = self.timed(retry_call(|| proxy.call("getDPI", &()))).await?;
+        // Some daemon versions append extra values after X and Y; only the first two matter.
+        match dpi[..] {
+            [dpi_x] => Ok(Dpi {
+                dpi_x: dpi_x as u16,
                 dpi_y: 0,
             }),
-            2 => Ok(Dpi {
-                dpi_x: dpi[0] as u16,
-                dpi_y: dpi[1] as u16,
+            [dpi_x, dpi_y, ..] => Ok(Dpi {
+                dpi_x: dpi_x as u16,
+                dpi_y: dpi_y as u16,
             }),
-            _ => Err(zbus::Error::Failure(
+            [] => Err(RazerError::InvalidReply(
                 "Invalid return array from DPI".to_string(),
             )),
         }
     }
 
-    pub async fn set_dpi_stages(&self, active_stage: u8, dpi_stages: Vec<Dpi>) -> zbus::Result<()> {
+    #[cfg(feature = "dpi")]
+    pub async fn set_dpi_stages(
+        &self,
+        active_stage: u8,
+        dpi_stages: Vec<Dpi>,
+    ) -> Result<(), RazerError> {
+        let max = self.max_dpi_stages().await?;
+        if dpi_stages.len() > usize::from(max) {
+            return Err(RazerError::TooManyDpiStages {
+                given: dpi_stages.len(),
+                max,
+            });
+        }
         let proxy = self.device_dpi_proxy().await?;
-        proxy
-            .call::<_, _, ()>("setDPIStages", &(active_stage, dpi_stages))
-            .await?;
+        self.timed(proxy.call::<_, _, ()>("setDPIStages", &(active_stage, dpi_stages))).await?;
         Ok(())
     }
 
-    pub async fn get_dpi_stages(&self) -> zbus::Result<(u8, Vec<Dpi>)> {
+    /// How many DPI stages the device can hold, from `maxDPIStages` where the daemon has it and
+    /// `DEFAULT_MAX_DPI_STAGES` otherwise.
+    #[cfg(feature = "dpi")]
+    pub async fn max_dpi_stages(&self) -> Result<u8, RazerError> {
+        if !self.has_capability_internal("razer.device.dpi", Some("maxDPIStages")) {
+            return Ok(DEFAULT_MAX_DPI_STAGES);
+        }
+        let proxy = self.device_dpi_proxy().await?;
+        self.timed(proxy.call("maxDPIStages", &())).await
+    }
+
+    #[cfg(feature = "dpi")]
+    pub async fn get_dpi_stages(&self) -> Result<(u8, Vec<Dpi>), RazerError> {
         let proxy = self.device_dpi_proxy().await?;
-        proxy.call("getDPIStages", &()).await
+        self.timed(proxy.call("getDPIStages", &())).await
     }
 
-    pub async fn max_dpi(&self) -> zbus::Result<u16> {
+    #[cfg(feature = "dpi")]
+    pub async fn max_dpi(&self) -> Result<u16, RazerError> {
         let proxy = self.device_dpi_proxy().await?;
-        let value: i32 = proxy.call("maxDPI", &()).await?;
+        let value: i32 = self.timed(proxy.call("maxDPI", &())).await?;
         Ok(value as u16)
     }
 
-    pub async fn get_battery_percent(&self) -> zbus::Result<f64> {
+    /// A zero TTL turns the cache off.
+    #[cfg(feature = "battery")]
+    pub fn with_battery_cache_ttl(mut self, ttl: Duration) -> Self {
+        self.battery_cache_ttl = ttl;
+        self
+    }
+
+    #[cfg(feature = "battery")]
+    pub(crate) fn with_shared_battery_cache(mut self, cache: SharedBatteryCache) -> Self {
+        self.battery_cache = cache;
+        self
+    }
+
+    /// Returns the last reading if it is younger than the cache TTL, otherwise reads it afresh.
+    #[cfg(feature = "battery")]
+    pub async fn get_battery_percent(&self) -> Result<f64, RazerError> {
+        let cached = self.battery_cache.lock().unwrap().get(self.object_path.as_str()).copied();
+        if let Some((read_at, percent)) = cached
+            && read_at.elapsed() < self.battery_cache_ttl
+        {
+            return Ok(percent);
+        }
+        self.refresh_battery_percent().await
+    }
+
+    /// Reads the battery from the daemon regardless of the cache, and caches the result.
+    #[cfg(feature = "battery")]
+    pub async fn refresh_battery_percent(&self) -> Result<f64, RazerError> {
+        let percent = self.read_battery_percent().await?;
+        self.battery_cache
+            .lock()
+            .unwrap()
+            .insert(self.object_path.to_string(), (Instant::now(), percent));
+        Ok(percent)
+    }
+
+    /// Every battery cell of the device. Until the daemon reports cells separately this is the
+    /// single `getBattery` reading.
+    #[cfg(feature = "battery")]
+    pub async fn get_batteries(&self) -> Result<Vec<BatteryCell>, RazerError> {
+        let percent = self.get_battery_percent().await?;
+        Ok(vec![BatteryCell { label: None, percent }])
+    }
+
+    #[cfg(feature = "battery")]
+    async fn read_battery_percent(&self) -> Result<f64, RazerError> {
         let proxy = self.device_power_proxy().await?;
-        proxy.call("getBattery", &()).await
+        // Older daemon/firmware combinations only expose an integer battery level.
+        if !self.has_capability_internal("razer.device.power", Some("getBattery"))
+            && self.has_capability_internal("razer.device.power", Some("getBatteryLevel"))
+        {
+            let level: i32 = self.timed(retry_call(|| proxy.call("getBatteryLevel", &()))).await?;
+            return Ok(f64::from(level));
+        }
+        let reply = self.timed(retry_call(|| proxy.call_method("getBattery", &()))).await?;
+        Self::battery_from_reply(&reply)
+    }
+
+    /// `getBattery` is documented as a double, but some daemon versions reply with an integer,
+    /// so any numeric reply is accepted rather than failing on the type.
+    #[cfg(feature = "battery")]
+    fn battery_from_reply(reply: &zbus::Message) -> Result<f64, RazerError> {
+        let body = reply.body();
+        let signature = body.signature().map(|signature| signature.to_string());
+        let percent = match signature.as_deref().unwrap_or_default() {
+            "d" => body.deserialize::<f64>()?,
+            "i" => f64::from(body.deserialize::<i32>()?),
+            "u" => f64::from(body.deserialize::<u32>()?),
+            "n" => f64::from(body.deserialize::<i16>()?),
+            "q" => f64::from(body.deserialize::<u16>()?),
+            "y" => f64::from(body.deserialize::<u8>()?),
+            other => {
+                return Err(RazerError::InvalidReply(format!(
+                    "Expected a number from getBattery, got type {other:?}"
+                )));
+            }
+        };
+        Ok(percent)
     }
 
-    pub async fn is_charging(&self) -> zbus::Result<bool> {
+    #[cfg(feature = "battery")]
+    pub async fn is_charging(&self) -> Result<bool, RazerError> {
         let proxy = self.device_power_proxy().await?;
-        proxy.call("isCharging", &()).await
+        self.timed(retry_call(|| proxy.call("isCharging", &()))).await
     }
 
-    pub async fn get_allowed_dpi(&self) -> zbus::Result<Vec<u16>> {
+    #[cfg(feature = "dpi")]
+    pub async fn get_allowed_dpi(&self) -> Result<Vec<u16>, RazerError> {
         let proxy = self.device_dpi_proxy().await?;
-        let values: Vec<i32> = proxy.call("availableDPI", &()).await?;
+        let values: Vec<i32> = self.timed(proxy.call("availableDPI", &())).await?;
         if values.is_empty() {
-            return Err(zbus::Error::Failure(
+            return Err(RazerError::InvalidReply(
                 "Invalid return array from availableDPI".to_string(),
             ));
         }
         Ok(values.into_iter().map(|value| value as u16).collect())
     }
 
-    pub async fn get_idle_time(&self) -> zbus::Result<u16> {
+    #[cfg(feature = "battery")]
+    pub async fn get_idle_time(&self) -> Result<u16, RazerError> {
         let proxy = self.device_power_proxy().await?;
-        let value: u16 = proxy.call("getIdleTime", &()).await?;
+        let value: u16 = self.timed(proxy.call("getIdleTime", &())).await?;
         Ok(value)
     }
 
-    pub async fn set_idle_time(&self, idle_time: u16) -> zbus::Result<()> {
+    #[cfg(feature = "battery")]
+    pub async fn set_idle_time(&self, idle_time: u16) -> Result<(), RazerError> {
         let proxy = self.device_power_proxy().await?;
-        proxy.call::<_, _, ()>("setIdleTime", &(idle_time)).await?;
+        self.timed(proxy.call::<_, _, ()>("setIdleTime", &(idle_time))).await?;
         Ok(())
     }
 
-    pub async fn get_low_battery_threshold(&self) -> zbus::Result<u8> {
+    #[cfg(feature = "battery")]
+    pub async fn get_low_battery_threshold(&self) -> Result<u8, RazerError> {
         let proxy = self.device_power_proxy().await?;
-        proxy.call("getLowBatteryThreshold", &()).await
+        self.timed(proxy.call("getLowBatteryThreshold", &())).await
     }
 
-    pub async fn set_low_battery_threshold(&self, threshold: f64) -> zbus::Result<()> {
+    #[cfg(feature = "battery")]
+    pub async fn set_low_battery_threshold(&self, threshold: f64) -> Result<(), RazerError> {
         let proxy = self.device_power_proxy().await?;
-        proxy.call::<_, _, ()>("setLowBatteryThreshold", &(threshold)).await?;
+        self.timed(proxy.call::<_, _, ()>("setLowBatteryThreshold", &(threshold))).await?;
         Ok(())
     }
 
-    pub async fn display_custom_frame(&self) -> zbus::Result<()> {
+    #[cfg(feature = "lighting")]
+    pub async fn display_custom_frame(&self) -> Result<(), RazerError> {
         let proxy = self.device_lighting_chroma_proxy().await?;
-        proxy.call::<_, _, ()>("setCustom", &()).await?;
+        self.timed(proxy.call::<_, _, ()>("setCustom", &())).await?;
         Ok(())
     }
 
+    /// Displays the custom frame and checks that the device went into driver mode. Some devices
+    /// drop the first `setCustom` while their firmware changes mode, so if the mode reads back
+    /// as anything else the call is issued once more. Devices that can't report their mode are
+    /// taken at their word.
+    #[cfg(feature = "lighting")]
+    pub async fn ensure_custom_mode(&self) -> Result<(), RazerError> {
+        self.display_custom_frame().await?;
+        let Ok(mode) = self.get_device_mode().await else {
+            return Ok(());
+        };
+        if mode == DRIVER_DEVICE_MODE {
+            return Ok(());
+        }
+        log::info!(
+            "{} reports device mode {mode} after setCustom; re-issuing it",
+            self.object_path.as_str()
+        );
+        self.display_custom_frame().await
+    }
+
+    /// Starts an effect that the daemon renders in software. It keeps the device in custom-frame
+    /// mode and runs until another effect is set, even after this process exits.
+    #[cfg(feature = "lighting")]
+    pub async fn start_software_effect(&self, effect: SoftwareEffect) -> Result<(), RazerError> {
+        let interface = "razer.device.lighting.custom";
+        let method = match effect {
+            SoftwareEffect::Ripple { .. } => "setRipple",
+            SoftwareEffect::RippleRandomColour { .. } => "setRippleRandomColour",
+        };
+        self.require_capability(interface, method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        match effect {
+            SoftwareEffect::Ripple { color, refresh_rate } => {
+                let args = (color.r, color.g, color.b, refresh_rate);
+                self.timed(proxy.call::<_, _, ()>(method, &args)).await?
+            }
+            SoftwareEffect::RippleRandomColour { refresh_rate } => {
+                self.timed(proxy.call::<_, _, ()>(method, &(refresh_rate))).await?
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops a software effect by switching back to the last custom frame, which is what the
+    /// daemon would show anyway once the animation is replaced.
+    #[cfg(feature = "lighting")]
+    pub async fn stop_software_effect(&self) -> Result<(), RazerError> {
+        self.display_custom_frame().await
+    }
+
+    #[cfg(feature = "lighting")]
     pub async fn define_custom_frame(
         &self,
         row: u8,
         start_column: u8,
         end_column: u8,
         color_data: Vec<Rgb>,
-    ) -> zbus::Result<()> {
-        let mut data = Vec::with_capacity(3 + color_data.len() * 3);
-        data.push(row);
-        data.push(start_column);
-        data.push(end_column);
-        for color in color_data {
-            data.push(color.r);
-            data.push(color.g);
-            data.push(color.b);
-        }
+    ) -> Result<(), RazerError> {
+        self.require_per_key_matrix()?;
+        let data = Self::frame_row_payload(row, start_column, end_column, &color_data);
         let proxy = self.device_lighting_chroma_proxy().await?;
-        proxy.call::<_, _, ()>("setKeyRow", &(data)).await?;
+        self.timed(proxy.call::<_, _, ()>("setKeyRow", &(data))).await?;
         Ok(())
     }
 
-    pub async fn get_matrix_dimensions(&self) -> zbus::Result<MatrixDimensions> {
-        let proxy = self.device_misc_proxy().await?;
-        let dims: Vec<i32> = proxy.call("getMatrixDimensions", &()).await?;
-        if dims.len() != 2 {
-            return Err(zbus::Error::Failure(
-                "Invalid return array from getMatrixDimensions".to_string(),
-            ));
+    /// Paints every matrix cell the same colour and displays it as a custom frame.
+    #[cfg(feature = "lighting")]
+    pub async fn fill_matrix(&self, color: Rgb) -> Result<(), RazerError> {
+        let dims = self.custom_frame_dimensions().await?;
+        for row in 0..dims.rows {
+            let colors = vec![color; usize::from(dims.columns)];
+            self.define_custom_frame(row, 0, dims.columns - 1, colors).await?;
         }
-        Ok(MatrixDimensions {
-            rows: dims[0] as u8,
-            columns: dims[1] as u8,
-        })
+        self.ensure_custom_mode().await
+    }
+
+    /// Sends every row of `frame` and displays it. Animations should follow up with
+    /// `set_matrix_diff`, which skips the custom-mode check.
+    #[cfg(feature = "lighting")]
+    pub async fn set_matrix(&self, frame: &Frame) -> Result<(), RazerError> {
+        self.require_per_key_matrix()?;
+        let dims = frame.dimensions();
+        let last_column = dims.columns.saturating_sub(1);
+        for row in 0..dims.rows {
+            self.define_custom_frame(row, 0, last_column, frame.row(row).to_vec())
+                .await?;
+        }
+        self.ensure_custom_mode().await
+    }
+
+    /// Like `set_matrix`, but only sends the part of each row that changed since `prev`, which
+    /// keeps animations from flooding the daemon with unchanged rows. Both frames must be the
+    /// same size; send a resized frame with `set_matrix` instead.
+    #[cfg(feature = "lighting")]
+    pub async fn set_matrix_diff(&self, prev: &Frame, next: &Frame) -> Result<(), RazerError> {
+        self.require_per_key_matrix()?;
+        if prev.dimensions() != next.dimensions() {
+            return Err(RazerError::FrameSizeMismatch {
+                prev: prev.dimensions(),
+                next: next.dimensions(),
+            });
+        }
+        let spans = prev.changed_spans(next);
+        if spans.is_empty() {
+            return Ok(());
+        }
+        for (row, start, end) in spans {
+            let colors = next.row(row)[usize::from(start)..=usize::from(end)].to_vec();
+            self.define_custom_frame(row, start, end, colors).await?;
+        }
+        self.display_custom_frame().await
+    }
+
+    /// Fades from `start` on the leftmost column to `end` on the rightmost, on every row.
+    #[cfg(feature = "lighting")]
+    pub async fn gradient_matrix(&self, start: Rgb, end: Rgb) -> Result<(), RazerError> {
+        let dims = self.custom_frame_dimensions().await?;
+        let colors = Self::gradient_row(start, end, dims.columns);
+        for row in 0..dims.rows {
+            self.define_custom_frame(row, 0, dims.columns - 1, colors.clone())
+                .await?;
+        }
+        self.ensure_custom_mode().await
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn get_led_effect(&self, led: LedId) -> Result<LedEffect, RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("get{prefix}Effect");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        let code: u8 = self.timed(proxy.call(method.as_str(), &())).await?;
+        Ok(LedEffect::from(code))
+    }
+
+    /// The primary colour of the LED's current effect, read from `get<Prefix>EffectColors`. The
+    /// daemon returns this for any effect, so callers wanting the static colour should check
+    /// `get_led_effect` first.
+    #[cfg(feature = "lighting")]
+    pub async fn get_static_color(&self, led: LedId) -> Result<Rgb, RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("get{prefix}EffectColors");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        let colors: Vec<u8> = self.timed(proxy.call(method.as_str(), &())).await?;
+        match colors[..] {
+            [r, g, b, ..] => Ok(Rgb { r, g, b }),
+            _ => Err(RazerError::InvalidReply(format!(
+                "Expected at least 3 bytes from {method}, got {}",
+                colors.len()
+            ))),
+        }
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_static(&self, led: LedId, color: Rgb) -> Result<(), RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("set{prefix}Static");
+        // Profile LEDs are plain on/off indicators with no colour of their own.
+        if Self::is_profile_led(led) {
+            return Err(RazerError::unsupported(interface, &method));
+        }
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call::<_, _, ()>(method.as_str(), &(color.r, color.g, color.b))).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_none(&self, led: LedId) -> Result<(), RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("set{prefix}None");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call::<_, _, ()>(method.as_str(), &())).await?;
+        Ok(())
+    }
+
+    /// Switches an LED on or off with its `set<Prefix>Active` method, which some devices expose
+    /// for status LEDs in place of (or next to) effects.
+    #[cfg(feature = "lighting")]
+    pub async fn set_led_active(&self, led: LedId, on: bool) -> Result<(), RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("set{prefix}Active");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call::<_, _, ()>(method.as_str(), &(on))).await?;
+        Ok(())
+    }
+
+    /// Sets `color` on every LED with a static effect and returns the names of those that took
+    /// it. LEDs without one are skipped, so they appear in neither list.
+    #[cfg(feature = "lighting")]
+    pub async fn set_all_static(&self, color: Rgb) -> Result<Vec<String>, RazerError> {
+        let mut applied = Vec::new();
+        let mut failures = Vec::new();
+        for (led, name) in &self.supported_leds {
+            let (interface, prefix) = Self::led_interface_and_prefix(*led);
+            if !self.has_capability_internal(interface, Some(&format!("set{prefix}Static"))) {
+                continue;
+            }
+            match self.set_static(*led, color).await {
+                Ok(()) => applied.push(name.clone()),
+                Err(err) => failures.push((name.clone(), err)),
+            }
+        }
+        applied.sort();
+        if failures.is_empty() {
+            return Ok(applied);
+        }
+        failures.sort_by(|a, b| a.0.cmp(&b.0));
+        Err(RazerError::PartialFailure { applied, leds: failures })
+    }
+
+    /// Legacy single-zone lighting on 2013-era BlackWidows, which predate the chroma interface.
+    #[cfg(feature = "lighting")]
+    pub async fn set_blinking(&self, color: Rgb) -> Result<(), RazerError> {
+        self.require_capability("razer.device.lighting.bw2013", "setBlinking")?;
+        let proxy = self.interface_proxy("razer.device.lighting.bw2013").await?;
+        self.timed(proxy.call::<_, _, ()>("setBlinking", &(color.r, color.g, color.b))).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_pulsate(&self) -> Result<(), RazerError> {
+        self.require_capability("razer.device.lighting.bw2013", "setPulsate")?;
+        let proxy = self.interface_proxy("razer.device.lighting.bw2013").await?;
+        self.timed(proxy.call::<_, _, ()>("setPulsate", &())).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_legacy_static(&self) -> Result<(), RazerError> {
+        self.require_capability("razer.device.lighting.bw2013", "setStatic")?;
+        let proxy = self.interface_proxy("razer.device.lighting.bw2013").await?;
+        self.timed(proxy.call::<_, _, ()>("setStatic", &())).await?;
+        Ok(())
+    }
+
+    /// Returns the daemon's description of the running matrix effect. The JSON schema isn't
+    /// stable and varies between OpenRazer versions, so it is passed through unparsed.
+    #[cfg(feature = "lighting")]
+    pub async fn get_active_matrix_effect(&self) -> Result<Value, RazerError> {
+        let interface = "razer.device.lighting.chroma";
+        let method = ["getActiveEffect", "getMatrixEffect"]
+            .into_iter()
+            .find(|method| self.has_capability_internal(interface, Some(method)))
+            .ok_or_else(|| RazerError::unsupported(interface, "getActiveEffect"))?;
+        let proxy = self.device_lighting_chroma_proxy().await?;
+        let payload: String = self.timed(proxy.call(method, &())).await?;
+        serde_json::from_str(&payload).map_err(|err| RazerError::Parse(err.to_string()))
+    }
+
+    #[cfg(feature = "lighting")]
+    pub fn supports_brightness(&self, led: LedId) -> bool {
+        let (interface, method) = Self::brightness_method(led, "get");
+        self.has_capability_internal(interface, Some(&method))
+    }
+
+    /// Brightness as a whole percentage. The daemon stores it as a 0-255 byte, so setting 50
+    /// reads back as 49.8; rounding to the nearest percent (halves away from zero) makes a value
+    /// read back the same as it was set. Use `get_brightness_raw` for the daemon's exact value.
+    #[cfg(feature = "lighting")]
+    pub async fn get_brightness(&self, led: LedId) -> Result<f64, RazerError> {
+        Ok(Self::round_brightness(self.get_brightness_raw(led).await?))
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn get_brightness_raw(&self, led: LedId) -> Result<f64, RazerError> {
+        let (interface, method) = Self::brightness_method(led, "get");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(retry_call(|| proxy.call(method.as_str(), &()))).await
+    }
+
+    #[cfg(feature = "lighting")]
+    fn round_brightness(raw: f64) -> f64 {
+        raw.round().clamp(0.0, 100.0)
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_brightness(&self, led: LedId, brightness: f64) -> Result<(), RazerError> {
+        let (interface, method) = Self::brightness_method(led, "set");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call::<_, _, ()>(method.as_str(), &(brightness))).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn get_game_mode(&self) -> Result<bool, RazerError> {
+        self.require_capability("razer.device.led.gamemode", "getGameMode")?;
+        let proxy = self.interface_proxy("razer.device.led.gamemode").await?;
+        self.timed(proxy.call("getGameMode", &())).await
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn set_game_mode(&self, enabled: bool) -> Result<(), RazerError> {
+        self.require_capability("razer.device.led.gamemode", "setGameMode")?;
+        let proxy = self.interface_proxy("razer.device.led.gamemode").await?;
+        self.timed(proxy.call::<_, _, ()>("setGameMode", &(enabled))).await?;
+        Ok(())
+    }
+
+    /// Whether one of the red, green or blue profile LEDs is lit. Other LEDs are `Unsupported`.
+    #[cfg(feature = "lighting")]
+    pub async fn get_profile_led(&self, led: LedId) -> Result<bool, RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("get{prefix}LED");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call(method.as_str(), &())).await
+    }
+
+    /// Turns one of the red, green or blue profile LEDs on or off. Keyboards with them use them
+    /// to show the active macro profile. Other LEDs are `Unsupported`.
+    #[cfg(feature = "lighting")]
+    pub async fn set_profile_led(&self, led: LedId, on: bool) -> Result<(), RazerError> {
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        let method = format!("set{prefix}LED");
+        self.require_capability(interface, &method)?;
+        let proxy = self.interface_proxy(interface).await?;
+        self.timed(proxy.call::<_, _, ()>(method.as_str(), &(on))).await?;
+        Ok(())
+    }
+
+    #[cfg(feature = "lighting")]
+    pub async fn get_matrix_dimensions(&self) -> Result<MatrixDimensions, RazerError> {
+        let dims = self
+            .matrix_dimensions
+            .get_or_try_init(|| async {
+                let proxy = self.device_misc_proxy().await?;
+                let dims: Vec<i32> = self.timed(proxy.call("getMatrixDimensions", &())).await?;
+                if dims.len() != 2 {
+                    return Err(RazerError::InvalidReply(
+                        "Invalid return array from getMatrixDimensions".to_string(),
+                    ));
+                }
+                Ok(MatrixDimensions {
+                    rows: dims[0] as u8,
+                    columns: dims[1] as u8,
+                })
+            })
+            .await?;
+        Ok(*dims)
+    }
+
+    /// Cached row count, or `None` until `get_matrix_dimensions` has been called once.
+    #[cfg(feature = "lighting")]
+    pub fn matrix_rows(&self) -> Option<u8> {
+        self.matrix_dimensions.get().map(|dims| dims.rows)
+    }
+
+    /// Cached column count, or `None` until `get_matrix_dimensions` has been called once.
+    #[cfg(feature = "lighting")]
+    pub fn matrix_cols(&self) -> Option<u8> {
+        self.matrix_dimensions.get().map(|dims| dims.columns)
+    }
+
+    /// Matrix `(row, column)` of a key such as `"W"` or `"space"` on this keyboard's layout, or
+    /// `None` if the key or layout isn't mapped. The layout is cached by `get_keyboard_layout`,
+    /// so this returns `None` until that has been called once.
+    #[cfg(feature = "lighting")]
+    pub fn key_position(&self, key: &str) -> Option<(u8, u8)> {
+        keymap::key_position(self.keyboard_layout.get()?, key)
     }
 
     async fn introspect(
         connection: &Connection,
         object_path: &OwnedObjectPath,
-    ) -> zbus::Result<HashSet<String>> {
+        call_timeout: Duration,
+    ) -> Result<HashSet<String>, RazerError> {
+        let xml = Self::introspection_xml(connection, object_path, call_timeout).await?;
+        Self::parse_introspection(&xml)
+    }
+
+    async fn introspection_xml(
+        connection: &Connection,
+        object_path: &OwnedObjectPath,
+        call_timeout: Duration,
+    ) -> Result<String, RazerError> {
         let proxy = Proxy::new(
             connection,
             OPENRAZER_SERVICE_NAME,
@@ -265,14 +960,17 @@ impl Device {
             "org.freedesktop.DBus.Introspectable",
         )
         .await?;
-        let xml: String = proxy.call("Introspect", &()).await?;
-        let sanitized = Self::strip_doctype(&xml);
+        with_timeout(call_timeout, proxy.call("Introspect", &())).await
+    }
+
+    fn parse_introspection(xml: &str) -> Result<HashSet<String>, RazerError> {
+        let sanitized = Self::strip_doctype(xml);
         let doc = Document::parse(&sanitized);
 
         let doc = match doc {
             Ok(doc) => doc,
             Err(err) => {
-                return Err(zbus::Error::Failure(format!(
+                return Err(RazerError::Parse(format!(
                     "Failed to parse introspection XML: {}",
                     err
                 )))
@@ -336,7 +1034,16 @@ impl Device {
         if self.has_capability_internal("razer.device.lighting.chroma", Some("setCustom")) {
             self.supported_features.insert("custom_frame".to_string());
         }
-        if self.has_capability_internal("razer.device.power", Some("getBattery")) {
+        // Whole-device custom mode is not enough for per-key effects, which also need rows
+        // written one at a time and the matrix size to lay them out.
+        if self.has_capability_internal("razer.device.lighting.chroma", Some("setKeyRow"))
+            && self.has_capability_internal("razer.device.misc", Some("getMatrixDimensions"))
+        {
+            self.supported_features.insert("per_key_matrix".to_string());
+        }
+        if self.has_capability_internal("razer.device.power", Some("getBattery"))
+            || self.has_capability_internal("razer.device.power", Some("getBatteryLevel"))
+        {
             self.supported_features.insert("battery".to_string());
         }
         if self.has_capability_internal("razer.device.power", Some("getLowBatteryThreshold")) {
@@ -346,7 +1053,15 @@ impl Device {
         if self.has_capability_internal("razer.device.power", Some("getIdleTime")) {
             self.supported_features.insert("idle_time".to_string());
         }
+        if self.has_capability_internal("razer.device.led.gamemode", Some("setGameMode")) {
+            self.supported_features.insert("game_mode".to_string());
+        }
+        #[cfg(feature = "lighting")]
+        self.setup_leds();
+    }
 
+    #[cfg(feature = "lighting")]
+    fn setup_leds(&mut self) {
         if self.has_capability_internal("razer.device.lighting.chroma", Some("setNone"))
             || self.has_capability_internal("razer.device.lighting.chroma", Some("setStatic"))
             || self.has_capability_internal("razer.device.lighting.bw2013", None)
@@ -401,6 +1116,112 @@ impl Device {
         }
     }
 
+    // Only the lighting methods check capabilities up front so far.
+    #[cfg_attr(not(feature = "lighting"), allow(dead_code))]
+    fn require_capability(&self, interface: &str, method: &str) -> Result<(), RazerError> {
+        if self.has_capability_internal(interface, Some(method)) {
+            Ok(())
+        } else {
+            Err(RazerError::unsupported(interface, method))
+        }
+    }
+
+    /// Maps an LED to its D-Bus interface and the prefix its methods use, e.g. `setLogoStatic`.
+    /// Profile LEDs only have on/off methods (`setRedLED`, see `set_profile_led`), so effect
+    /// methods built from their prefix are never advertised and fail the capability check.
+    #[cfg(feature = "lighting")]
+    fn led_interface_and_prefix(led: LedId) -> (&'static str, &'static str) {
+        match led {
+            LedId::Unspecified => ("razer.device.lighting.chroma", ""),
+            LedId::LogoLED => ("razer.device.lighting.logo", "Logo"),
+            LedId::ScrollWheelLED => ("razer.device.lighting.scroll", "Scroll"),
+            LedId::BacklightLED => ("razer.device.lighting.backlight", "Backlight"),
+            LedId::LeftSideLED => ("razer.device.lighting.left", "Left"),
+            LedId::RightSideLED => ("razer.device.lighting.right", "Right"),
+            LedId::KeymapRedLED => ("razer.device.lighting.profile_led", "Red"),
+            LedId::KeymapGreenLED => ("razer.device.lighting.profile_led", "Green"),
+            LedId::KeymapBlueLED => ("razer.device.lighting.profile_led", "Blue"),
+            LedId::ChargingLED => ("razer.device.lighting.charging", "Charging"),
+            LedId::FastChargingLED => ("razer.device.lighting.fast_charging", "FastCharging"),
+            LedId::FullyChargedLED => ("razer.device.lighting.fully_charged", "FullyCharged"),
+        }
+    }
+
+    #[cfg(feature = "lighting")]
+    fn is_profile_led(led: LedId) -> bool {
+        matches!(led, LedId::KeymapRedLED | LedId::KeymapGreenLED | LedId::KeymapBlueLED)
+    }
+
+    // Whole-device brightness lives on its own interface rather than alongside the chroma effects.
+    #[cfg(feature = "lighting")]
+    fn brightness_method(led: LedId, verb: &str) -> (&'static str, String) {
+        if led == LedId::Unspecified {
+            return ("razer.device.lighting.brightness", format!("{verb}Brightness"));
+        }
+        let (interface, prefix) = Self::led_interface_and_prefix(led);
+        (interface, format!("{verb}{prefix}Brightness"))
+    }
+
+    #[cfg(feature = "lighting")]
+    async fn custom_frame_dimensions(&self) -> Result<MatrixDimensions, RazerError> {
+        self.require_per_key_matrix()?;
+        let dims = self.get_matrix_dimensions().await?;
+        if dims.rows == 0 || dims.columns == 0 {
+            return Err(RazerError::InvalidReply(
+                "Matrix dimensions are empty".to_string(),
+            ));
+        }
+        Ok(dims)
+    }
+
+    #[cfg(feature = "lighting")]
+    fn require_per_key_matrix(&self) -> Result<(), RazerError> {
+        if self.has_feature("per_key_matrix") {
+            Ok(())
+        } else {
+            Err(RazerError::unsupported("razer.device.lighting.chroma", "setKeyRow"))
+        }
+    }
+
+    #[cfg(feature = "lighting")]
+    fn frame_row_payload(row: u8, start_column: u8, end_column: u8, colors: &[Rgb]) -> Vec<u8> {
+        let mut data = Vec::with_capacity(3 + colors.len() * 3);
+        data.push(row);
+        data.push(start_column);
+        data.push(end_column);
+        for color in colors {
+            data.push(color.r);
+            data.push(color.g);
+            data.push(color.b);
+        }
+        data
+    }
+
+    #[cfg(feature = "lighting")]
+    fn gradient_row(start: Rgb, end: Rgb, columns: u8) -> Vec<Rgb> {
+        let steps = f32::from(columns.saturating_sub(1).max(1));
+        let mix = |from: u8, to: u8, t: f32| {
+            (f32::from(from) + (f32::from(to) - f32::from(from)) * t).round() as u8
+        };
+        (0..columns)
+            .map(|column| {
+                let t = f32::from(column) / steps;
+                Rgb {
+                    r: mix(start.r, end.r, t),
+                    g: mix(start.g, end.g, t),
+                    b: mix(start.b, end.b, t),
+                }
+            })
+            .collect()
+    }
+
+    async fn timed<T>(
+        &self,
+        call: impl Future<Output = zbus::Result<T>>,
+    ) -> Result<T, RazerError> {
+        with_timeout(self.call_timeout, call).await
+    }
+
     fn has_capability_internal(&self, interface: &str, method: Option<&str>) -> bool {
         match method {
             Some(method) => self
@@ -420,6 +1241,7 @@ impl Device {
         .await
     }
 
+    #[cfg(feature = "dpi")]
     async fn device_dpi_proxy(&self) -> zbus::Result<Proxy<'_>> {
         Proxy::new(
             &self.connection,
@@ -430,6 +1252,7 @@ impl Device {
         .await
     }
 
+    #[cfg(feature = "battery")]
     async fn device_power_proxy(&self) -> zbus::Result<Proxy<'_>> {
         Proxy::new(
             &self.connection,
@@ -440,6 +1263,18 @@ impl Device {
         .await
     }
 
+    #[cfg(feature = "lighting")]
+    async fn interface_proxy(&self, interface: &'static str) -> zbus::Result<Proxy<'_>> {
+        Proxy::new(
+            &self.connection,
+            OPENRAZER_SERVICE_NAME,
+            self.object_path.as_str(),
+            interface,
+        )
+        .await
+    }
+
+    #[cfg(feature = "lighting")]
     async fn device_lighting_chroma_proxy(&self) -> zbus::Result<Proxy<'_>> {
         Proxy::new(
             &self.connection,
@@ -450,3 +1285,224 @@ impl Device {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OPENRAZER_XML: &str = r#"<!DOCTYPE node PUBLIC "-//freedesktop//DTD D-BUS Object Introspection 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/introspect.dtd">
+<node name="/org/razer/device/PM1234567890123">
+  <interface name="org.freedesktop.DBus.Introspectable">
+    <method name="Introspect">
+      <arg direction="out" type="s" />
+    </method>
+  </interface>
+  <interface name="razer.device.misc">
+    <method name="getSerial">
+      <arg direction="out" type="s" />
+    </method>
+    <method name="getDeviceName">
+      <arg direction="out" type="s" />
+    </method>
+  </interface>
+  <interface name="razer.device.power">
+    <method name="getBattery">
+      <arg direction="out" type="d" />
+    </method>
+    <method name="isCharging">
+      <arg direction="out" type="b" />
+    </method>
+  </interface>
+</node>
+"#;
+
+    #[test]
+    fn serial_comes_from_the_device_path() {
+        assert_eq!(serial_from_path("/org/razer/device/PM1234567890123"), Some("PM1234567890123"));
+        assert_eq!(serial_from_path("/org/razer/device/"), None);
+        assert_eq!(serial_from_path("/org/razer/device/PM1/extra"), None);
+        assert_eq!(serial_from_path("/org/razer"), None);
+        assert_eq!(serial_from_path("/org/other/device/PM1"), None);
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn frame_row_payload_length_matches_columns() {
+        let start = Rgb { r: 255, g: 0, b: 0 };
+        let end = Rgb { r: 0, g: 0, b: 255 };
+        for columns in [1u8, 15, 22] {
+            let colors = Device::gradient_row(start, end, columns);
+            let payload = Device::frame_row_payload(0, 0, columns - 1, &colors);
+            assert_eq!(payload.len(), 3 + usize::from(columns) * 3);
+            assert_eq!(&payload[..3], &[0, 0, columns - 1]);
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn brightness_rounds_halves_up_and_stays_in_range() {
+        assert_eq!(Device::round_brightness(49.8), 50.0);
+        assert_eq!(Device::round_brightness(49.5), 50.0);
+        assert_eq!(Device::round_brightness(49.49), 49.0);
+        assert_eq!(Device::round_brightness(0.5), 1.0);
+        assert_eq!(Device::round_brightness(0.4), 0.0);
+        assert_eq!(Device::round_brightness(99.5), 100.0);
+        assert_eq!(Device::round_brightness(100.4), 100.0);
+        assert_eq!(Device::round_brightness(-0.6), 0.0);
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn gradient_row_spans_both_ends() {
+        let start = Rgb { r: 255, g: 0, b: 0 };
+        let end = Rgb { r: 0, g: 0, b: 255 };
+        let colors = Device::gradient_row(start, end, 5);
+        assert_eq!(colors.first(), Some(&start));
+        assert_eq!(colors.last(), Some(&end));
+        assert_eq!(colors[2], Rgb { r: 128, g: 0, b: 128 });
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn resolves_led_interfaces_for_every_led() {
+        let cases = [
+            (LedId::Unspecified, "razer.device.lighting.chroma", "", "setStatic"),
+            (LedId::LogoLED, "razer.device.lighting.logo", "Logo", "setLogoStatic"),
+            (LedId::ScrollWheelLED, "razer.device.lighting.scroll", "Scroll", "setScrollStatic"),
+            (
+                LedId::BacklightLED,
+                "razer.device.lighting.backlight",
+                "Backlight",
+                "setBacklightStatic",
+            ),
+            (LedId::LeftSideLED, "razer.device.lighting.left", "Left", "setLeftStatic"),
+            (LedId::RightSideLED, "razer.device.lighting.right", "Right", "setRightStatic"),
+            (
+                LedId::ChargingLED,
+                "razer.device.lighting.charging",
+                "Charging",
+                "setChargingStatic",
+            ),
+            (
+                LedId::FastChargingLED,
+                "razer.device.lighting.fast_charging",
+                "FastCharging",
+                "setFastChargingStatic",
+            ),
+            (
+                LedId::FullyChargedLED,
+                "razer.device.lighting.fully_charged",
+                "FullyCharged",
+                "setFullyChargedStatic",
+            ),
+        ];
+        for (led, interface, prefix, static_method) in cases {
+            assert_eq!(Device::led_interface_and_prefix(led), (interface, prefix), "{led:?}");
+            assert_eq!(format!("set{prefix}Static"), static_method, "{led:?}");
+        }
+        let profile_leds = [
+            (LedId::KeymapRedLED, "Red"),
+            (LedId::KeymapGreenLED, "Green"),
+            (LedId::KeymapBlueLED, "Blue"),
+        ];
+        for (led, prefix) in profile_leds {
+            let resolved = Device::led_interface_and_prefix(led);
+            assert_eq!(resolved, ("razer.device.lighting.profile_led", prefix), "{led:?}");
+            assert!(Device::is_profile_led(led), "{led:?}");
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn scroll_wheel_brightness_uses_scroll_interface() {
+        let (interface, method) = Device::brightness_method(LedId::ScrollWheelLED, "set");
+        assert_eq!(interface, "razer.device.lighting.scroll");
+        assert_eq!(method, "setScrollBrightness");
+
+        let (interface, method) = Device::brightness_method(LedId::Unspecified, "get");
+        assert_eq!(interface, "razer.device.lighting.brightness");
+        assert_eq!(method, "getBrightness");
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn partial_failure_names_each_failed_led() {
+        let err = RazerError::PartialFailure {
+            applied: vec!["Backlight".to_string()],
+            leds: vec![
+                ("Logo".to_string(), RazerError::InvalidReply("bad".to_string())),
+                ("Scroll".to_string(), RazerError::unsupported("razer.x", "setScrollStatic")),
+            ],
+        };
+        assert_eq!(
+            err.to_string(),
+            "Failed on Logo (invalid reply: bad), \
+             Scroll (Device does not support razer.x;setScrollStatic)"
+        );
+    }
+
+    #[test]
+    fn parses_openrazer_introspection() {
+        let entries = Device::parse_introspection(OPENRAZER_XML).unwrap();
+        for expected in [
+            "razer.device.misc",
+            "razer.device.misc;getSerial",
+            "razer.device.misc;getDeviceName",
+            "razer.device.power",
+            "razer.device.power;getBattery",
+            "razer.device.power;isCharging",
+            "org.freedesktop.DBus.Introspectable;Introspect",
+        ] {
+            assert!(entries.contains(expected), "missing {expected}");
+        }
+        assert_eq!(entries.len(), 8);
+    }
+
+    #[test]
+    fn strips_multi_line_doctype() {
+        let stripped = Device::strip_doctype(OPENRAZER_XML);
+        assert!(!stripped.contains("DOCTYPE"));
+        assert!(!stripped.contains("introspect.dtd"));
+        assert!(stripped.starts_with("<node"));
+    }
+
+    #[test]
+    fn strips_single_line_doctype() {
+        let xml = "<!DOCTYPE node>\n<node/>\n";
+        assert_eq!(Device::strip_doctype(xml), "<node/>\n");
+    }
+
+    #[test]
+    fn rejects_malformed_xml() {
+        let err = Device::parse_introspection("<node><interface name=\"x\">").unwrap_err();
+        assert!(matches!(err, RazerError::Parse(_)));
+    }
+
+    #[test]
+    fn keeps_interfaces_without_methods() {
+        let xml = r#"<node><interface name="razer.device.lighting.logo"></interface></node>"#;
+        let entries = Device::parse_introspection(xml).unwrap();
+        assert_eq!(
+            entries,
+            HashSet::from(["razer.device.lighting.logo".to_string()])
+        );
+    }
+
+    #[test]
+    fn skips_interfaces_without_names() {
+        let xml = r#"<node>
+  <interface><method name="orphan"/></interface>
+  <interface name=""><method name="blank"/></interface>
+  <interface name="razer.device.dpi"><method name="getDPI"/></interface>
+</node>"#;
+        let entries = Device::parse_introspection(xml).unwrap();
+        assert_eq!(
+            entries,
+            HashSet::from([
+                "razer.device.dpi".to_string(),
+                "razer.device.dpi;getDPI".to_string(),
+            ])
+        );
+    }
+}
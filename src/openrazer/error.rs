@@ -0,0 +1,107 @@
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "dpi")]
+use crate::openrazer::types::Dpi;
+#[cfg(feature = "lighting")]
+use crate::openrazer::types::MatrixDimensions;
+
+#[derive(Debug)]
+pub enum RazerError {
+    Dbus(zbus::Error),
+    Parse(String),
+    /// The device didn't advertise the method, so callers can hide the related controls.
+    Unsupported { interface: String, method: String },
+    InvalidReply(String),
+    /// The daemon didn't answer within the call timeout.
+    Timeout(Duration),
+    /// The device accepted a DPI change but reads back something else, e.g. after clamping.
+    #[cfg(feature = "dpi")]
+    DpiMismatch { requested: Dpi, applied: Dpi },
+    /// More DPI stages were given than the device can hold.
+    #[cfg(feature = "dpi")]
+    TooManyDpiStages { given: usize, max: u8 },
+    /// No device with this serial is attached.
+    NotFound { serial: String },
+    /// An all-LED change failed on `leds` and went through on `applied`, both named as in
+    /// `supported_leds`.
+    #[cfg(feature = "lighting")]
+    PartialFailure { applied: Vec<String>, leds: Vec<(String, RazerError)> },
+    /// Two frames meant to be diffed against each other have different sizes.
+    #[cfg(feature = "lighting")]
+    FrameSizeMismatch { prev: MatrixDimensions, next: MatrixDimensions },
+}
+
+impl RazerError {
+    pub(crate) fn unsupported(interface: &str, method: &str) -> Self {
+        RazerError::Unsupported {
+            interface: interface.to_string(),
+            method: method.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RazerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RazerError::Dbus(err) => write!(f, "{err}"),
+            RazerError::Parse(message) => write!(f, "parse error: {message}"),
+            RazerError::Unsupported { interface, method } => {
+                write!(f, "Device does not support {interface};{method}")
+            }
+            RazerError::InvalidReply(message) => write!(f, "invalid reply: {message}"),
+            RazerError::Timeout(limit) => {
+                write!(f, "D-Bus call timed out after {}s", limit.as_secs_f32())
+            }
+            #[cfg(feature = "dpi")]
+            RazerError::DpiMismatch { requested, applied } => write!(
+                f,
+                "DPI set to {}x{} but the device reports {}x{}",
+                requested.dpi_x, requested.dpi_y, applied.dpi_x, applied.dpi_y
+            ),
+            #[cfg(feature = "dpi")]
+            RazerError::TooManyDpiStages { given, max } => {
+                write!(f, "{given} DPI stages given but the device supports at most {max}")
+            }
+            RazerError::NotFound { serial } => {
+                write!(f, "No OpenRazer device with serial {serial} is present")
+            }
+            #[cfg(feature = "lighting")]
+            RazerError::PartialFailure { leds, .. } => {
+                write!(f, "Failed on ")?;
+                for (index, (name, err)) in leds.iter().enumerate() {
+                    let separator = if index == 0 { "" } else { ", " };
+                    write!(f, "{separator}{name} ({err})")?;
+                }
+                Ok(())
+            }
+            #[cfg(feature = "lighting")]
+            RazerError::FrameSizeMismatch { prev, next } => write!(
+                f,
+                "Can't diff a {}x{} frame against a {}x{} one",
+                prev.rows, prev.columns, next.rows, next.columns
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RazerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RazerError::Dbus(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<zbus::Error> for RazerError {
+    fn from(err: zbus::Error) -> Self {
+        RazerError::Dbus(err)
+    }
+}
+
+impl From<zbus::zvariant::Error> for RazerError {
+    fn from(err: zbus::zvariant::Error) -> Self {
+        RazerError::Dbus(err.into())
+    }
+}
@@ -0,0 +1,145 @@
+//! Matrix coordinates of keyboard keys, so effects can target keys by name. Positions follow the
+//! 6x22 matrix full-size OpenRazer keyboards use; smaller boards leave some cells unused.
+
+/// `(label, row, column)` for a US ANSI keyboard.
+const US: &[(&str, u8, u8)] = &[
+    ("esc", 0, 1),
+    ("f1", 0, 3),
+    ("f2", 0, 4),
+    ("f3", 0, 5),
+    ("f4", 0, 6),
+    ("f5", 0, 7),
+    ("f6", 0, 8),
+    ("f7", 0, 9),
+    ("f8", 0, 10),
+    ("f9", 0, 11),
+    ("f10", 0, 12),
+    ("f11", 0, 13),
+    ("f12", 0, 14),
+    ("printscreen", 0, 15),
+    ("scrolllock", 0, 16),
+    ("pause", 0, 17),
+    ("logo", 0, 20),
+    ("m1", 1, 0),
+    ("`", 1, 1),
+    ("1", 1, 2),
+    ("2", 1, 3),
+    ("3", 1, 4),
+    ("4", 1, 5),
+    ("5", 1, 6),
+    ("6", 1, 7),
+    ("7", 1, 8),
+    ("8", 1, 9),
+    ("9", 1, 10),
+    ("0", 1, 11),
+    ("-", 1, 12),
+    ("=", 1, 13),
+    ("backspace", 1, 14),
+    ("insert", 1, 15),
+    ("home", 1, 16),
+    ("pageup", 1, 17),
+    ("numlock", 1, 18),
+    ("num/", 1, 19),
+    ("num*", 1, 20),
+    ("num-", 1, 21),
+    ("m2", 2, 0),
+    ("tab", 2, 1),
+    ("q", 2, 2),
+    ("w", 2, 3),
+    ("e", 2, 4),
+    ("r", 2, 5),
+    ("t", 2, 6),
+    ("y", 2, 7),
+    ("u", 2, 8),
+    ("i", 2, 9),
+    ("o", 2, 10),
+    ("p", 2, 11),
+    ("[", 2, 12),
+    ("]", 2, 13),
+    ("\\", 2, 14),
+    ("delete", 2, 15),
+    ("end", 2, 16),
+    ("pagedown", 2, 17),
+    ("num7", 2, 18),
+    ("num8", 2, 19),
+    ("num9", 2, 20),
+    ("num+", 2, 21),
+    ("m3", 3, 0),
+    ("capslock", 3, 1),
+    ("a", 3, 2),
+    ("s", 3, 3),
+    ("d", 3, 4),
+    ("f", 3, 5),
+    ("g", 3, 6),
+    ("h", 3, 7),
+    ("j", 3, 8),
+    ("k", 3, 9),
+    ("l", 3, 10),
+    (";", 3, 11),
+    ("'", 3, 12),
+    ("enter", 3, 14),
+    ("num4", 3, 18),
+    ("num5", 3, 19),
+    ("num6", 3, 20),
+    ("m4", 4, 0),
+    ("leftshift", 4, 1),
+    ("z", 4, 3),
+    ("x", 4, 4),
+    ("c", 4, 5),
+    ("v", 4, 6),
+    ("b", 4, 7),
+    ("n", 4, 8),
+    ("m", 4, 9),
+    (",", 4, 10),
+    (".", 4, 11),
+    ("/", 4, 12),
+    ("rightshift", 4, 14),
+    ("up", 4, 16),
+    ("num1", 4, 18),
+    ("num2", 4, 19),
+    ("num3", 4, 20),
+    ("numenter", 4, 21),
+    ("m5", 5, 0),
+    ("leftctrl", 5, 1),
+    ("super", 5, 2),
+    ("leftalt", 5, 3),
+    ("space", 5, 7),
+    ("rightalt", 5, 11),
+    ("fn", 5, 12),
+    ("menu", 5, 13),
+    ("rightctrl", 5, 14),
+    ("left", 5, 15),
+    ("down", 5, 16),
+    ("right", 5, 17),
+    ("num0", 5, 19),
+    ("num.", 5, 20),
+];
+
+/// Looks a key up by label (case-insensitive) for a layout name as returned by
+/// `Device::get_keyboard_layout`. Only the US layout is mapped so far.
+pub fn key_position(layout: &str, key: &str) -> Option<(u8, u8)> {
+    let table = match layout {
+        "US" => US,
+        _ => return None,
+    };
+    table
+        .iter()
+        .find(|(label, _, _)| label.eq_ignore_ascii_case(key))
+        .map(|&(_, row, column)| (row, column))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_wasd_on_the_us_layout() {
+        let wasd: Vec<_> = ["W", "a", "S", "d"]
+            .into_iter()
+            .map(|key| key_position("US", key))
+            .collect();
+        assert_eq!(wasd, vec![Some((2, 3)), Some((3, 2)), Some((3, 3)), Some((3, 4))]);
+        assert_eq!(key_position("US", "nope"), None);
+        assert_eq!(key_position("German", "w"), None);
+    }
+}
@@ -1,82 +1,531 @@
+#[cfg(feature = "battery")]
+use std::collections::HashMap;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use serde::Deserialize;
 use serde_json::Value;
+use tokio::sync::broadcast;
+#[cfg(feature = "battery")]
+use tokio::time::MissedTickBehavior;
+#[cfg(feature = "battery")]
+use tokio_stream::wrappers::IntervalStream;
+#[cfg(feature = "battery")]
+use tokio_stream::{Stream, StreamExt};
 use zbus::fdo::DBusProxy;
 use zbus::names::BusName;
-use zbus::{Connection, Proxy};
 use zbus::zvariant::OwnedObjectPath;
+use zbus::{Connection, Proxy};
+
+#[cfg(feature = "battery")]
+use crate::openrazer::device::SharedBatteryCache;
+use crate::openrazer::timeout::{with_timeout, DEFAULT_CALL_TIMEOUT};
+#[cfg(feature = "battery")]
+use crate::openrazer::types::BatteryCell;
+use crate::openrazer::{
+    serial_from_path, Device, DeviceType, RazerError, OPENRAZER_ROOT_PATH, OPENRAZER_SERVICE_NAME,
+};
 
-use crate::openrazer::{Device, OPENRAZER_ROOT_PATH, OPENRAZER_SERVICE_NAME};
+/// How many events a slow subscriber can fall behind by before it starts missing them.
+const EVENT_CAPACITY: usize = 64;
 
 pub struct Manager {
     connection: Connection,
+    bus: BusKind,
+    events: broadcast::Sender<DeviceEvent>,
+    event_state: Mutex<EventState>,
+    call_timeout: Duration,
+    #[cfg(feature = "battery")]
+    battery_cache_ttl: Duration,
+    #[cfg(feature = "battery")]
+    battery_cache: SharedBatteryCache,
+    #[cfg(feature = "battery")]
+    device_order: DeviceOrder,
+}
+
+/// A change noticed between two polls, broadcast to every `Manager::events` subscriber.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceEvent {
+    DeviceAdded { serial: String },
+    DeviceRemoved { serial: String },
+    #[cfg(feature = "battery")]
+    BatteryChanged { serial: String, percent: u8 },
+    #[cfg(feature = "battery")]
+    ChargingChanged { serial: String, charging: bool },
+}
+
+/// What the previous `publish_*` calls saw, so that only differences become events.
+#[derive(Default)]
+struct EventState {
+    devices: HashSet<String>,
+    #[cfg(feature = "battery")]
+    batteries: HashMap<String, (u8, bool)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BusKind {
+    Session,
+    /// Used by some distributions that run the daemon as a system service.
+    System,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SupportedDevice {
+    pub name: String,
+    /// The daemon's device family, e.g. `keyboards` or `mouse`.
+    pub device_type: String,
+    pub vid: u16,
+    pub pid: u16,
+}
+
+/// How `Manager::battery_summary` orders its devices.
+#[cfg(feature = "battery")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DeviceOrder {
+    /// As the daemon lists them, which isn't stable across restarts.
+    #[default]
+    Daemon,
+    /// Mice, then keyboards, then headsets and everything else, by name within each type.
+    Type,
+    Name,
+}
+
+#[cfg(feature = "battery")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatterySummary {
+    pub name: String,
+    pub serial: String,
+    pub device_type: DeviceType,
+    /// The lowest of `cells`, rounded.
+    pub percent: u8,
+    pub charging: bool,
+    pub cells: Vec<BatteryCell>,
+}
+
+#[cfg(feature = "battery")]
+impl BatterySummary {
+    /// Each cell on its own, e.g. "L 80% / R 75%", for devices with more than one battery.
+    pub fn cells_text(&self) -> Option<String> {
+        if self.cells.len() < 2 {
+            return None;
+        }
+        let cells: Vec<_> = self
+            .cells
+            .iter()
+            .enumerate()
+            .map(|(index, cell)| {
+                let percent = cell.percent.round().clamp(0.0, 100.0);
+                match &cell.label {
+                    Some(label) => format!("{label} {percent}%"),
+                    None => format!("#{} {percent}%", index + 1),
+                }
+            })
+            .collect();
+        Some(cells.join(" / "))
+    }
 }
 
 impl Manager {
-    pub async fn new() -> zbus::Result<Self> {
-        let connection = Connection::session().await?;
-        Ok(Self { connection })
+    /// Connects to whichever bus the daemon is running on, preferring the session bus. If the
+    /// daemon isn't running anywhere yet, the session bus is used so it can be picked up later.
+    pub async fn new() -> Result<Self, RazerError> {
+        let session = Self::new_with_bus(BusKind::Session).await;
+        if let Ok(manager) = &session
+            && manager.is_daemon_running().await.unwrap_or(false)
+        {
+            log::info!("Found the OpenRazer daemon on the session bus");
+            return session;
+        }
+        if let Ok(manager) = Self::new_with_bus(BusKind::System).await
+            && manager.is_daemon_running().await.unwrap_or(false)
+        {
+            log::info!("Found the OpenRazer daemon on the system bus");
+            return Ok(manager);
+        }
+        log::info!("OpenRazer daemon is not running; waiting for it on the session bus");
+        session
+    }
+
+    pub async fn new_with_bus(bus: BusKind) -> Result<Self, RazerError> {
+        let connection = match bus {
+            BusKind::Session => Connection::session().await?,
+            BusKind::System => Connection::system().await?,
+        };
+        Ok(Self::from_connection(connection, bus))
+    }
+
+    /// Wraps a connection made elsewhere, e.g. a peer-to-peer one in tests. `bus` is only
+    /// reported back by `bus()`.
+    pub fn from_connection(connection: Connection, bus: BusKind) -> Self {
+        Self {
+            connection,
+            bus,
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            event_state: Mutex::new(EventState::default()),
+            call_timeout: DEFAULT_CALL_TIMEOUT,
+            #[cfg(feature = "battery")]
+            battery_cache_ttl: crate::openrazer::device::DEFAULT_BATTERY_CACHE_TTL,
+            #[cfg(feature = "battery")]
+            battery_cache: SharedBatteryCache::default(),
+            #[cfg(feature = "battery")]
+            device_order: DeviceOrder::default(),
+        }
+    }
+
+    pub fn bus(&self) -> BusKind {
+        self.bus
+    }
+
+    /// Limits every D-Bus call made by this manager and the devices it opens from now on.
+    pub fn set_call_timeout(&mut self, timeout: Duration) {
+        self.call_timeout = timeout;
+    }
+
+    /// Battery cache TTL given to every device this manager opens from now on.
+    #[cfg(feature = "battery")]
+    pub fn set_battery_cache_ttl(&mut self, ttl: Duration) {
+        self.battery_cache_ttl = ttl;
+    }
+
+    /// Order used by `battery_summary`, and so by `battery_stream`, from now on.
+    #[cfg(feature = "battery")]
+    pub fn set_device_order(&mut self, order: DeviceOrder) {
+        self.device_order = order;
     }
 
     pub fn connection(&self) -> &Connection {
         &self.connection
     }
 
-    pub async fn is_daemon_running(&self) -> zbus::Result<bool> {
+    /// Subscribes to device changes. Nothing is sent until a poller calls the `publish_*`
+    /// methods, and events published before subscribing are not replayed.
+    pub fn events(&self) -> broadcast::Receiver<DeviceEvent> {
+        self.events.subscribe()
+    }
+
+    /// Publishes `DeviceAdded`/`DeviceRemoved` for the difference from the previous call.
+    pub fn publish_devices(&self, paths: &[OwnedObjectPath]) {
+        let current: HashSet<String> = paths
+            .iter()
+            .filter_map(|path| serial_from_path(path.as_str()))
+            .map(str::to_string)
+            .collect();
+        let mut state = self.event_state.lock().unwrap();
+        let events = Self::device_changes(&state.devices, &current);
+        state.devices = current;
+        drop(state);
+        self.send_events(events);
+    }
+
+    /// Publishes `BatteryChanged`/`ChargingChanged` for the difference from the previous call.
+    /// A device seen for the first time gets both, so subscribers learn its initial state.
+    #[cfg(feature = "battery")]
+    pub fn publish_batteries(&self, summaries: &[BatterySummary]) {
+        let mut state = self.event_state.lock().unwrap();
+        let events = Self::battery_changes(&state.batteries, summaries);
+        state.batteries = summaries
+            .iter()
+            .map(|summary| (summary.serial.clone(), (summary.percent, summary.charging)))
+            .collect();
+        drop(state);
+        self.send_events(events);
+    }
+
+    pub async fn is_daemon_running(&self) -> Result<bool, RazerError> {
         let proxy = DBusProxy::new(&self.connection).await?;
         let name = BusName::try_from(OPENRAZER_SERVICE_NAME)
-            .map_err(|err| zbus::Error::Failure(err.to_string()))?;
-        proxy.name_has_owner(name).await.map_err(Into::into)
+            .map_err(|err| RazerError::Parse(err.to_string()))?;
+        self.timed(async { proxy.name_has_owner(name).await.map_err(zbus::Error::from) })
+            .await
     }
 
-    pub async fn get_supported_devices(&self) -> zbus::Result<Value> {
+    pub async fn get_supported_devices(&self) -> Result<Value, RazerError> {
         let proxy = self.devices_proxy().await?;
-        let payload: String = proxy.call("supportedDevices", &()).await?;
+        let payload: String = self.timed(proxy.call("supportedDevices", &())).await?;
         let value = serde_json::from_str(&payload)
-            .map_err(|err| zbus::Error::Failure(err.to_string()))?;
+            .map_err(|err| Self::supported_devices_error(&payload, err))?;
         Ok(value)
     }
 
-    pub async fn get_devices(&self) -> zbus::Result<Vec<OwnedObjectPath>> {
+    /// Typed view of `get_supported_devices`, sorted by device type and then name.
+    pub async fn supported_devices(&self) -> Result<Vec<SupportedDevice>, RazerError> {
+        let proxy = self.devices_proxy().await?;
+        let payload: String = self.timed(proxy.call("supportedDevices", &())).await?;
+        Self::parse_supported_devices(&payload)
+    }
+
+    pub async fn get_devices(&self) -> Result<Vec<OwnedObjectPath>, RazerError> {
         let proxy = self.devices_proxy().await?;
-        let serials: Vec<String> = proxy.call("getDevices", &()).await?;
+        let serials: Vec<String> = self.timed(proxy.call("getDevices", &())).await?;
         let mut out = Vec::with_capacity(serials.len());
         for serial in serials {
-            let path = format!("/org/razer/device/{serial}");
-            let object_path = OwnedObjectPath::try_from(path)
-                .map_err(|err| zbus::Error::Failure(err.to_string()))?;
-            out.push(object_path);
+            out.push(Self::device_path(&serial)?);
+        }
+        Ok(out)
+    }
+
+    pub async fn get_device(&self, object_path: OwnedObjectPath) -> Result<Device, RazerError> {
+        let device =
+            Device::new_with_timeout(self.connection.clone(), object_path, self.call_timeout)
+                .await?;
+        #[cfg(feature = "battery")]
+        let device = device
+            .with_battery_cache_ttl(self.battery_cache_ttl)
+            .with_shared_battery_cache(self.battery_cache.clone());
+        Ok(device)
+    }
+
+    /// Opens every device the daemon lists, skipping any that vanish before they can be
+    /// introspected. Callers that need several views of the devices can share one listing.
+    pub async fn open_devices(&self) -> Result<Vec<Device>, RazerError> {
+        let mut out = Vec::new();
+        for path in self.get_devices().await? {
+            if let Ok(device) = self.get_device(path).await {
+                out.push(device);
+            }
         }
         Ok(out)
     }
 
-    pub async fn get_device(&self, object_path: OwnedObjectPath) -> zbus::Result<Device> {
-        Device::new(self.connection.clone(), object_path).await
+    pub async fn get_device_by_serial(&self, serial: &str) -> Result<Device, RazerError> {
+        let device = self.get_device(Self::device_path(serial)?).await?;
+        if device.capabilities().contains("razer.device.misc") {
+            Ok(device)
+        } else {
+            Err(RazerError::NotFound { serial: serial.to_string() })
+        }
     }
 
-    pub async fn sync_effects(&self, yes: bool) -> zbus::Result<()> {
+    pub async fn get_devices_by_type(&self, kind: DeviceType) -> Result<Vec<Device>, RazerError> {
+        let mut out = Vec::new();
+        for path in self.get_devices().await? {
+            let device = match self.get_device(path).await {
+                Ok(device) => device,
+                Err(_) => continue,
+            };
+            let device_type = match device.get_device_type().await {
+                Ok(device_type) => device_type,
+                Err(_) => continue,
+            };
+            if DeviceType::from(device_type.as_str()) == kind {
+                out.push(device);
+            }
+        }
+        Ok(out)
+    }
+
+    /// Reads every device that reports a battery, in the configured `DeviceOrder`. Devices that
+    /// vanish or fail part way through are skipped.
+    #[cfg(feature = "battery")]
+    pub async fn battery_summary(&self) -> Result<Vec<BatterySummary>, RazerError> {
+        let devices = self.open_devices().await?;
+        Ok(self.summarize_batteries(&devices).await)
+    }
+
+    /// Like `battery_summary`, but for devices the caller already opened.
+    #[cfg(feature = "battery")]
+    pub async fn summarize_batteries(
+        &self,
+        devices: impl IntoIterator<Item = &Device>,
+    ) -> Vec<BatterySummary> {
+        let mut out = Vec::new();
+        for device in devices {
+            // Wired devices can still expose the power interface, but only ever report 0%.
+            if !device.has_feature("battery") || !device.is_wireless().await.unwrap_or(true) {
+                continue;
+            }
+            let cells = match device.get_batteries().await {
+                Ok(cells) if !cells.is_empty() => cells,
+                _ => continue,
+            };
+            // The weakest cell is what runs out first.
+            let percent = cells.iter().map(|cell| cell.percent).fold(f64::INFINITY, f64::min);
+            let serial = match device.get_serial().await {
+                Ok(serial) => serial,
+                Err(_) => continue,
+            };
+            out.push(BatterySummary {
+                name: device
+                    .get_device_name()
+                    .await
+                    .unwrap_or_else(|_| "Unknown device".to_string()),
+                serial,
+                device_type: device
+                    .get_device_type()
+                    .await
+                    .map(|device_type| DeviceType::from(device_type.as_str()))
+                    .unwrap_or(DeviceType::Unknown(String::new())),
+                percent: percent.round().clamp(0.0, 100.0) as u8,
+                charging: device.is_charging().await.unwrap_or(false),
+                cells,
+            });
+        }
+        Self::sort_summaries(&mut out, self.device_order);
+        out
+    }
+
+    #[cfg(feature = "battery")]
+    fn sort_summaries(summaries: &mut [BatterySummary], order: DeviceOrder) {
+        let type_rank = |device_type: &DeviceType| match device_type {
+            DeviceType::Mouse => 0,
+            DeviceType::Keyboard => 1,
+            DeviceType::Headset => 2,
+            _ => 3,
+        };
+        // Serials break ties so two identical devices don't swap places between polls.
+        match order {
+            DeviceOrder::Daemon => {}
+            DeviceOrder::Type => summaries.sort_by(|a, b| {
+                (type_rank(&a.device_type), &a.name, &a.serial)
+                    .cmp(&(type_rank(&b.device_type), &b.name, &b.serial))
+            }),
+            DeviceOrder::Name => {
+                summaries.sort_by(|a, b| (&a.name, &a.serial).cmp(&(&b.name, &b.serial)))
+            }
+        }
+    }
+
+    /// Yields a fresh `battery_summary` straight away and then every `interval`. A daemon that
+    /// can't be reached yields an empty list rather than ending the stream.
+    #[cfg(feature = "battery")]
+    pub fn battery_stream(&self, interval: Duration) -> impl Stream<Item = Vec<BatterySummary>> {
+        let mut ticks = tokio::time::interval(interval);
+        // A slow daemon shouldn't lead to a burst of back-to-back reads once it catches up.
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        IntervalStream::new(ticks)
+            .then(move |_| async move { self.battery_summary().await.unwrap_or_default() })
+    }
+
+    pub async fn sync_effects(&self, yes: bool) -> Result<(), RazerError> {
         let proxy = self.devices_proxy().await?;
-        proxy.call::<_, _, ()>("syncEffects", &(yes)).await?;
+        self.timed(proxy.call::<_, _, ()>("syncEffects", &(yes))).await?;
         Ok(())
     }
 
-    pub async fn get_sync_effects(&self) -> zbus::Result<bool> {
+    pub async fn get_sync_effects(&self) -> Result<bool, RazerError> {
         let proxy = self.devices_proxy().await?;
-        proxy.call("getSyncEffects", &()).await
+        self.timed(proxy.call("getSyncEffects", &())).await
     }
 
-    pub async fn get_daemon_version(&self) -> zbus::Result<String> {
+    pub async fn get_daemon_version(&self) -> Result<String, RazerError> {
         let proxy = self.daemon_proxy().await?;
-        proxy.call("version", &()).await
+        self.timed(proxy.call("version", &())).await
     }
 
-    pub async fn set_turn_off_on_screensaver(&self, turn_off: bool) -> zbus::Result<()> {
+    pub async fn set_turn_off_on_screensaver(&self, turn_off: bool) -> Result<(), RazerError> {
         let proxy = self.devices_proxy().await?;
-        proxy.call::<_, _, ()>("enableTurnOffOnScreensaver", &(turn_off)).await?;
+        self.timed(proxy.call::<_, _, ()>("enableTurnOffOnScreensaver", &(turn_off))).await?;
         Ok(())
     }
 
-    pub async fn get_turn_off_on_screensaver(&self) -> zbus::Result<bool> {
+    pub async fn get_turn_off_on_screensaver(&self) -> Result<bool, RazerError> {
         let proxy = self.devices_proxy().await?;
-        proxy.call("getOffOnScreensaver", &()).await
+        self.timed(proxy.call("getOffOnScreensaver", &())).await
+    }
+
+    // The payload is `{"<type>": {"<name>": [vid, pid]}}`; older daemons wrote the IDs as hex
+    // strings rather than integers.
+    fn parse_supported_devices(payload: &str) -> Result<Vec<SupportedDevice>, RazerError> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum UsbId {
+            Number(u16),
+            Hex(String),
+        }
+
+        let parsed: BTreeMap<String, BTreeMap<String, (UsbId, UsbId)>> =
+            serde_json::from_str(payload)
+                .map_err(|err| Self::supported_devices_error(payload, err))?;
+        let parse_id = |id: UsbId| match id {
+            UsbId::Number(id) => Ok(id),
+            UsbId::Hex(hex) => {
+                u16::from_str_radix(hex.trim_start_matches("0x"), 16).map_err(|err| {
+                    RazerError::Parse(format!("Invalid USB id {hex:?} in supportedDevices: {err}"))
+                })
+            }
+        };
+        let mut out = Vec::new();
+        for (device_type, devices) in parsed {
+            for (name, (vid, pid)) in devices {
+                out.push(SupportedDevice {
+                    name,
+                    device_type: device_type.clone(),
+                    vid: parse_id(vid)?,
+                    pid: parse_id(pid)?,
+                });
+            }
+        }
+        Ok(out)
+    }
+
+    fn supported_devices_error(payload: &str, err: serde_json::Error) -> RazerError {
+        const SNIPPET_LEN: usize = 120;
+        let snippet: String = payload.chars().take(SNIPPET_LEN).collect();
+        let ellipsis = if payload.chars().count() > SNIPPET_LEN { "..." } else { "" };
+        RazerError::Parse(format!(
+            "Unexpected supportedDevices payload ({err}): {snippet}{ellipsis}"
+        ))
+    }
+
+    fn device_changes(previous: &HashSet<String>, current: &HashSet<String>) -> Vec<DeviceEvent> {
+        let mut added: Vec<&String> = current.difference(previous).collect();
+        let mut removed: Vec<&String> = previous.difference(current).collect();
+        added.sort();
+        removed.sort();
+        let added = added.into_iter().map(|serial| DeviceEvent::DeviceAdded {
+            serial: serial.clone(),
+        });
+        let removed = removed.into_iter().map(|serial| DeviceEvent::DeviceRemoved {
+            serial: serial.clone(),
+        });
+        removed.chain(added).collect()
+    }
+
+    #[cfg(feature = "battery")]
+    fn battery_changes(
+        previous: &HashMap<String, (u8, bool)>,
+        summaries: &[BatterySummary],
+    ) -> Vec<DeviceEvent> {
+        let mut events = Vec::new();
+        for summary in summaries {
+            let previous = previous.get(&summary.serial);
+            if previous.is_none_or(|&(percent, _)| percent != summary.percent) {
+                events.push(DeviceEvent::BatteryChanged {
+                    serial: summary.serial.clone(),
+                    percent: summary.percent,
+                });
+            }
+            if previous.is_none_or(|&(_, charging)| charging != summary.charging) {
+                events.push(DeviceEvent::ChargingChanged {
+                    serial: summary.serial.clone(),
+                    charging: summary.charging,
+                });
+            }
+        }
+        events
+    }
+
+    async fn timed<T>(
+        &self,
+        call: impl Future<Output = zbus::Result<T>>,
+    ) -> Result<T, RazerError> {
+        with_timeout(self.call_timeout, call).await
+    }
+
+    fn send_events(&self, events: Vec<DeviceEvent>) {
+        for event in events {
+            // Fails only when nobody is subscribed, which is fine.
+            let _ = self.events.send(event);
+        }
+    }
+
+    fn device_path(serial: &str) -> Result<OwnedObjectPath, RazerError> {
+        OwnedObjectPath::try_from(format!("{OPENRAZER_ROOT_PATH}/device/{serial}")).map_err(|err| {
+            RazerError::Parse(format!("Invalid device serial {serial:?}: {err}"))
+        })
     }
 
     async fn daemon_proxy(&self) -> zbus::Result<Proxy<'_>> {
@@ -99,3 +548,107 @@ impl Manager {
         .await
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_supported_devices() {
+        let payload = r#"{
+            "mouse": {"RazerDeathAdderV2": [5426, 132]},
+            "keyboards": {"RazerBlackWidowChroma": ["0x1532", "0x0203"]}
+        }"#;
+        let devices = Manager::parse_supported_devices(payload).unwrap();
+        assert_eq!(
+            devices,
+            vec![
+                SupportedDevice {
+                    name: "RazerBlackWidowChroma".to_string(),
+                    device_type: "keyboards".to_string(),
+                    vid: 0x1532,
+                    pid: 0x0203,
+                },
+                SupportedDevice {
+                    name: "RazerDeathAdderV2".to_string(),
+                    device_type: "mouse".to_string(),
+                    vid: 0x1532,
+                    pid: 0x0084,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn device_changes_lists_removals_then_additions() {
+        let set = |serials: &[&str]| serials.iter().map(|s| s.to_string()).collect();
+        let events = Manager::device_changes(&set(&["A", "B"]), &set(&["B", "D", "C"]));
+        assert_eq!(
+            events,
+            vec![
+                DeviceEvent::DeviceRemoved { serial: "A".to_string() },
+                DeviceEvent::DeviceAdded { serial: "C".to_string() },
+                DeviceEvent::DeviceAdded { serial: "D".to_string() },
+            ]
+        );
+        assert!(Manager::device_changes(&set(&["A"]), &set(&["A"])).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "battery")]
+    fn sorts_summaries_by_type_then_name() {
+        let summary = |name: &str, serial: &str, device_type| BatterySummary {
+            name: name.to_string(),
+            serial: serial.to_string(),
+            device_type,
+            percent: 50,
+            charging: false,
+            cells: Vec::new(),
+        };
+        let mut summaries = vec![
+            summary("Kraken", "H1", DeviceType::Headset),
+            summary("Viper", "M2", DeviceType::Mouse),
+            summary("BlackWidow", "K1", DeviceType::Keyboard),
+            summary("Basilisk", "M1", DeviceType::Mouse),
+        ];
+        let serials = |summaries: &[BatterySummary]| {
+            summaries.iter().map(|s| s.serial.clone()).collect::<Vec<_>>()
+        };
+
+        Manager::sort_summaries(&mut summaries, DeviceOrder::Type);
+        assert_eq!(serials(&summaries), ["M1", "M2", "K1", "H1"]);
+        Manager::sort_summaries(&mut summaries, DeviceOrder::Name);
+        assert_eq!(serials(&summaries), ["M1", "K1", "H1", "M2"]);
+    }
+
+    #[test]
+    #[cfg(feature = "battery")]
+    fn cells_text_lists_each_cell() {
+        let cell = |label: Option<&str>, percent| BatteryCell {
+            label: label.map(str::to_string),
+            percent,
+        };
+        let mut summary = BatterySummary {
+            name: "Kraken".to_string(),
+            serial: "H1".to_string(),
+            device_type: DeviceType::Headset,
+            percent: 75,
+            charging: false,
+            cells: vec![cell(None, 80.0)],
+        };
+        assert_eq!(summary.cells_text(), None);
+        summary.cells = vec![cell(Some("L"), 80.0), cell(Some("R"), 74.6)];
+        assert_eq!(summary.cells_text().as_deref(), Some("L 80% / R 75%"));
+        summary.cells = vec![cell(None, 80.0), cell(None, 75.0)];
+        assert_eq!(summary.cells_text().as_deref(), Some("#1 80% / #2 75%"));
+    }
+
+    #[test]
+    fn supported_devices_error_includes_payload_snippet() {
+        let payload = format!(r#"{{"mouse": ["unexpected"]}}{}"#, " ".repeat(200));
+        let err = Manager::parse_supported_devices(&payload).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains(r#"{"mouse": ["unexpected"]}"#), "{message}");
+        assert!(message.ends_with("..."), "{message}");
+    }
+}
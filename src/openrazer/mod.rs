@@ -1,10 +1,25 @@
 pub mod device;
+pub mod error;
+#[cfg(feature = "lighting")]
+pub mod keymap;
 pub mod manager;
+mod retry;
+mod timeout;
 pub mod types;
 
-pub use device::Device;
-pub use manager::Manager;
-pub use types::{Dpi, LedId, MatrixDimensions, Rgb};
+pub use device::{serial_from_path, Device};
+pub use error::RazerError;
+pub use timeout::DEFAULT_CALL_TIMEOUT;
+#[cfg(feature = "battery")]
+pub use manager::{BatterySummary, DeviceOrder};
+#[cfg(feature = "battery")]
+pub use types::BatteryCell;
+pub use manager::{BusKind, DeviceEvent, Manager, SupportedDevice};
+pub use types::{DeviceType, ParseError, Rgb};
+#[cfg(feature = "dpi")]
+pub use types::Dpi;
+#[cfg(feature = "lighting")]
+pub use types::{Frame, LedEffect, LedId, MatrixDimensions, SoftwareEffect};
 
 pub const OPENRAZER_SERVICE_NAME: &str = "org.razer";
 pub const OPENRAZER_ROOT_PATH: &str = "/org/razer";
@@ -0,0 +1,45 @@
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+
+use zbus::fdo;
+
+const MAX_ATTEMPTS: u32 = 3;
+const BACKOFF: Duration = Duration::from_millis(100);
+
+/// Runs a D-Bus call, retrying with a short backoff when the daemon is too busy to answer.
+/// Errors such as unknown methods are returned straight away so capability gaps stay visible.
+pub(crate) async fn retry_call<T, F, Fut>(mut call: F) -> zbus::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = zbus::Result<T>>,
+{
+    let mut attempt = 1;
+    loop {
+        match call().await {
+            Err(err) if attempt < MAX_ATTEMPTS && is_transient(&err) => {
+                log::debug!("Transient D-Bus error on attempt {attempt}, retrying: {err}");
+                tokio::time::sleep(BACKOFF * attempt).await;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(err: &zbus::Error) -> bool {
+    match err {
+        zbus::Error::InputOutput(err) => err.kind() == io::ErrorKind::TimedOut,
+        zbus::Error::MethodError(name, _, _) => matches!(
+            name.as_str(),
+            "org.freedesktop.DBus.Error.NoReply"
+                | "org.freedesktop.DBus.Error.Timeout"
+                | "org.freedesktop.DBus.Error.TimedOut"
+        ),
+        zbus::Error::FDO(err) => matches!(
+            **err,
+            fdo::Error::NoReply(_) | fdo::Error::Timeout(_) | fdo::Error::TimedOut(_)
+        ),
+        _ => false,
+    }
+}
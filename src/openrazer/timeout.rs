@@ -0,0 +1,18 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::openrazer::error::RazerError;
+
+/// How long a single D-Bus call may take before a wedged daemon is given up on.
+pub const DEFAULT_CALL_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Runs a D-Bus call, failing with `RazerError::Timeout` if it doesn't finish within `limit`.
+pub(crate) async fn with_timeout<T, Fut>(limit: Duration, call: Fut) -> Result<T, RazerError>
+where
+    Fut: Future<Output = zbus::Result<T>>,
+{
+    match tokio::time::timeout(limit, call).await {
+        Ok(result) => Ok(result?),
+        Err(_) => Err(RazerError::Timeout(limit)),
+    }
+}
@@ -1,25 +1,192 @@
+use std::fmt;
+
 use serde::{Deserialize, Serialize};
 use zvariant::Type;
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type)]
+#[cfg(feature = "dpi")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub struct Dpi {
     pub dpi_x: u16,
     pub dpi_y: u16,
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Type)]
+#[cfg(feature = "dpi")]
+impl Dpi {
+    pub fn uniform(value: u16) -> Dpi {
+        Dpi {
+            dpi_x: value,
+            dpi_y: value,
+        }
+    }
+
+    /// A `dpi_y` of 0 means the device only has a single DPI axis.
+    pub fn is_valid_for(&self, max: u16) -> bool {
+        (1..=max).contains(&self.dpi_x) && self.dpi_y <= max
+    }
+
+    pub fn clamp(self, max: u16) -> Dpi {
+        Dpi {
+            dpi_x: self.dpi_x.min(max),
+            dpi_y: self.dpi_y.min(max),
+        }
+    }
+}
+
+/// One battery of a device. Today's daemons report a single, unlabelled cell; headsets with a
+/// battery per ear would report one labelled cell each ("L", "R").
+#[cfg(feature = "battery")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct BatteryCell {
+    pub label: Option<String>,
+    pub percent: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Type)]
 pub struct Rgb {
     pub r: u8,
     pub g: u8,
     pub b: u8,
 }
 
-#[derive(Debug, Clone, Copy)]
+impl Rgb {
+    /// Roughly 2700K. Razer LEDs run blue, so this reads as a neutral white on most devices.
+    pub fn warm_white() -> Rgb {
+        Rgb {
+            r: 255,
+            g: 169,
+            b: 87,
+        }
+    }
+
+    /// Roughly 6500K, for a crisper white than `warm_white` without the blue cast of pure white.
+    pub fn cool_white() -> Rgb {
+        Rgb {
+            r: 255,
+            g: 236,
+            b: 204,
+        }
+    }
+
+    /// Parses `#RRGGBB` or `RRGGBB`, in either case.
+    pub fn from_hex(hex: &str) -> Result<Rgb, ParseError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return Err(ParseError::InvalidDigit(hex.to_string()));
+        }
+        if digits.len() != 6 {
+            return Err(ParseError::InvalidLength(digits.len()));
+        }
+        let channel = |start: usize| u8::from_str_radix(&digits[start..start + 2], 16).unwrap();
+        Ok(Rgb {
+            r: channel(0),
+            g: channel(2),
+            b: channel(4),
+        })
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02X}{:02X}{:02X}", self.r, self.g, self.b)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseError {
+    InvalidLength(usize),
+    InvalidDigit(String),
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::InvalidLength(len) => {
+                write!(f, "expected 6 hex digits but found {len}")
+            }
+            ParseError::InvalidDigit(value) => write!(f, "invalid hex colour {value:?}"),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct MatrixDimensions {
     pub rows: u8,
     pub columns: u8,
 }
 
+/// One colour per matrix cell, row by row, for building custom frames.
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    dimensions: MatrixDimensions,
+    cells: Vec<Rgb>,
+}
+
+#[cfg(feature = "lighting")]
+impl Frame {
+    /// A frame with every cell off.
+    pub fn new(dimensions: MatrixDimensions) -> Frame {
+        let len = usize::from(dimensions.rows) * usize::from(dimensions.columns);
+        Frame {
+            dimensions,
+            cells: vec![Rgb { r: 0, g: 0, b: 0 }; len],
+        }
+    }
+
+    pub fn dimensions(&self) -> MatrixDimensions {
+        self.dimensions
+    }
+
+    pub fn get(&self, row: u8, column: u8) -> Option<Rgb> {
+        self.index(row, column).map(|index| self.cells[index])
+    }
+
+    /// Cells outside the matrix are ignored.
+    pub fn set(&mut self, row: u8, column: u8, color: Rgb) {
+        if let Some(index) = self.index(row, column) {
+            self.cells[index] = color;
+        }
+    }
+
+    pub fn row(&self, row: u8) -> &[Rgb] {
+        let columns = usize::from(self.dimensions.columns);
+        let start = usize::from(row) * columns;
+        self.cells.get(start..start + columns).unwrap_or(&[])
+    }
+
+    /// The `(row, first column, last column)` span of every row that differs between the two
+    /// frames, narrowed to the changed columns. Frames of different sizes differ everywhere, and
+    /// a frame without columns has nothing to send.
+    pub fn changed_spans(&self, next: &Frame) -> Vec<(u8, u8, u8)> {
+        if next.dimensions.columns == 0 {
+            return Vec::new();
+        }
+        let last_column = next.dimensions.columns - 1;
+        (0..next.dimensions.rows)
+            .filter_map(|row| {
+                if self.dimensions != next.dimensions {
+                    return Some((row, 0, last_column));
+                }
+                let (before, after) = (self.row(row), next.row(row));
+                let changed = |column: &usize| before[*column] != after[*column];
+                let first = (0..after.len()).find(changed)?;
+                let last = (0..after.len()).rev().find(changed)?;
+                Some((row, first as u8, last as u8))
+            })
+            .collect()
+    }
+
+    fn index(&self, row: u8, column: u8) -> Option<usize> {
+        (row < self.dimensions.rows && column < self.dimensions.columns).then(|| {
+            usize::from(row) * usize::from(self.dimensions.columns) + usize::from(column)
+        })
+    }
+}
+
+#[cfg(feature = "lighting")]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum LedId {
     Unspecified,
@@ -35,3 +202,150 @@ pub enum LedId {
     FastChargingLED,
     FullyChargedLED,
 }
+
+/// Effects the daemon animates itself by streaming custom frames to the device.
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SoftwareEffect {
+    Ripple { color: Rgb, refresh_rate: f64 },
+    RippleRandomColour { refresh_rate: f64 },
+}
+
+#[cfg(feature = "lighting")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LedEffect {
+    Static,
+    Blinking,
+    Breathing,
+    Spectrum,
+    Unknown(u8),
+}
+
+#[cfg(feature = "lighting")]
+impl From<u8> for LedEffect {
+    fn from(code: u8) -> Self {
+        match code {
+            0x00 => LedEffect::Static,
+            0x01 => LedEffect::Blinking,
+            0x02 => LedEffect::Breathing,
+            0x04 => LedEffect::Spectrum,
+            other => LedEffect::Unknown(other),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DeviceType {
+    Accessory,
+    Headset,
+    Keyboard,
+    Keypad,
+    Mouse,
+    Mousepad,
+    Unknown(String),
+}
+
+impl From<&str> for DeviceType {
+    fn from(device_type: &str) -> Self {
+        match device_type {
+            "accessory" => DeviceType::Accessory,
+            "headset" => DeviceType::Headset,
+            "keyboard" => DeviceType::Keyboard,
+            "keypad" => DeviceType::Keypad,
+            "mouse" => DeviceType::Mouse,
+            "mousepad" => DeviceType::Mousepad,
+            other => DeviceType::Unknown(other.to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rgb_from_hex_accepts_either_case() {
+        let expected = Rgb { r: 0xab, g: 0xcd, b: 0xef };
+        assert_eq!(Rgb::from_hex("#abcdef"), Ok(expected));
+        assert_eq!(Rgb::from_hex("#ABCDEF"), Ok(expected));
+    }
+
+    #[test]
+    fn rgb_from_hex_allows_missing_hash() {
+        assert_eq!(Rgb::from_hex("FF0080"), Ok(Rgb { r: 255, g: 0, b: 128 }));
+    }
+
+    #[test]
+    fn rgb_from_hex_rejects_invalid_input() {
+        assert_eq!(Rgb::from_hex("#fff"), Err(ParseError::InvalidLength(3)));
+        assert_eq!(Rgb::from_hex("#1234567"), Err(ParseError::InvalidLength(7)));
+        assert!(matches!(Rgb::from_hex("#12345g"), Err(ParseError::InvalidDigit(_))));
+        assert!(matches!(Rgb::from_hex("+1+2+3"), Err(ParseError::InvalidDigit(_))));
+    }
+
+    #[test]
+    #[cfg(feature = "dpi")]
+    fn dpi_clamp_keeps_values_at_the_limit() {
+        assert_eq!(Dpi::uniform(16000).clamp(16000), Dpi::uniform(16000));
+        assert!(Dpi::uniform(16000).is_valid_for(16000));
+    }
+
+    #[test]
+    #[cfg(feature = "dpi")]
+    fn dpi_clamp_limits_values_beyond_the_max() {
+        let dpi = Dpi {
+            dpi_x: 16001,
+            dpi_y: 30000,
+        };
+        assert!(!dpi.is_valid_for(16000));
+        assert_eq!(dpi.clamp(16000), Dpi::uniform(16000));
+        assert!(dpi.clamp(16000).is_valid_for(16000));
+    }
+
+    #[test]
+    #[cfg(feature = "dpi")]
+    fn dpi_single_axis_is_valid() {
+        let dpi = Dpi {
+            dpi_x: 800,
+            dpi_y: 0,
+        };
+        assert!(dpi.is_valid_for(16000));
+        assert_eq!(dpi.clamp(400), Dpi { dpi_x: 400, dpi_y: 0 });
+        assert!(!Dpi::uniform(0).is_valid_for(16000));
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn identical_frames_have_no_changed_rows() {
+        let mut frame = Frame::new(MatrixDimensions { rows: 6, columns: 22 });
+        frame.set(2, 5, Rgb { r: 255, g: 0, b: 0 });
+        assert!(frame.changed_spans(&frame.clone()).is_empty());
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn changed_spans_cover_only_the_changed_columns() {
+        let before = Frame::new(MatrixDimensions { rows: 6, columns: 22 });
+        let mut after = before.clone();
+        after.set(1, 3, Rgb { r: 255, g: 0, b: 0 });
+        after.set(1, 7, Rgb { r: 0, g: 255, b: 0 });
+        after.set(4, 21, Rgb { r: 0, g: 0, b: 255 });
+        assert_eq!(before.changed_spans(&after), vec![(1, 3, 7), (4, 21, 21)]);
+    }
+
+    #[test]
+    #[cfg(feature = "lighting")]
+    fn frames_without_columns_have_no_spans() {
+        let before = Frame::new(MatrixDimensions { rows: 6, columns: 22 });
+        let empty = Frame::new(MatrixDimensions { rows: 6, columns: 0 });
+        assert!(before.changed_spans(&empty).is_empty());
+        assert_eq!(empty.changed_spans(&before).len(), 6);
+    }
+
+    #[test]
+    fn rgb_display_round_trips() {
+        let color = Rgb { r: 1, g: 0xa0, b: 0xff };
+        assert_eq!(color.to_string(), "#01A0FF");
+        assert_eq!(Rgb::from_hex(&color.to_string()), Ok(color));
+    }
+}
@@ -0,0 +1,109 @@
+use std::time::Duration;
+
+use rusty_razer_tray::openrazer::BatterySummary;
+
+/// How many unchanged polls in a row before the interval starts to stretch.
+const STABLE_POLLS: u32 = 5;
+/// Batteries within this many points of the low threshold are watched at the fastest rate.
+const NEAR_LOW_MARGIN: u8 = 5;
+
+/// Picks the wait before the next poll: `min` while anything is happening (a level changed, a
+/// device is charging or close to running low) and doubling towards `max` while every battery
+/// holds steady.
+pub struct AdaptiveInterval {
+    min: Duration,
+    max: Duration,
+    current: Duration,
+    stable_polls: u32,
+    last: Vec<(String, u8, bool)>,
+}
+
+impl AdaptiveInterval {
+    pub fn new(min: Duration, max: Duration) -> Self {
+        Self {
+            min,
+            max: max.max(min),
+            current: min,
+            stable_polls: 0,
+            last: Vec::new(),
+        }
+    }
+
+    pub fn next(&mut self, summaries: &[BatterySummary], low_threshold: u8) -> Duration {
+        let readings: Vec<_> = summaries
+            .iter()
+            .map(|summary| (summary.serial.clone(), summary.percent, summary.charging))
+            .collect();
+        let busy = summaries.iter().any(|summary| {
+            summary.charging || summary.percent <= low_threshold.saturating_add(NEAR_LOW_MARGIN)
+        });
+        if busy || readings != self.last {
+            self.last = readings;
+            self.stable_polls = 0;
+            self.current = self.min;
+            return self.current;
+        }
+        self.stable_polls += 1;
+        if self.stable_polls >= STABLE_POLLS {
+            self.current = (self.current * 2).min(self.max);
+        }
+        self.current
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rusty_razer_tray::openrazer::DeviceType;
+
+    use super::*;
+
+    const MIN: Duration = Duration::from_secs(1);
+    const MAX: Duration = Duration::from_secs(10);
+
+    fn summary(percent: u8, charging: bool) -> BatterySummary {
+        BatterySummary {
+            name: "Viper".to_string(),
+            serial: "PM0000000000001".to_string(),
+            device_type: DeviceType::Mouse,
+            percent,
+            charging,
+            cells: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn backs_off_to_the_maximum_while_steady() {
+        let mut interval = AdaptiveInterval::new(MIN, MAX);
+        let steady = [summary(80, false)];
+        let waits: Vec<_> = (0..10).map(|_| interval.next(&steady, 20)).collect();
+        assert_eq!(waits[..STABLE_POLLS as usize], [MIN; STABLE_POLLS as usize]);
+        assert_eq!(waits[STABLE_POLLS as usize], MIN * 2);
+        assert_eq!(waits.last(), Some(&MAX));
+    }
+
+    #[test]
+    fn a_change_drops_back_to_the_minimum() {
+        let mut interval = AdaptiveInterval::new(MIN, MAX);
+        for _ in 0..10 {
+            interval.next(&[summary(80, false)], 20);
+        }
+        assert_eq!(interval.next(&[summary(79, false)], 20), MIN);
+    }
+
+    #[test]
+    fn charging_and_nearly_low_batteries_stay_at_the_minimum() {
+        let mut interval = AdaptiveInterval::new(MIN, MAX);
+        for _ in 0..10 {
+            assert_eq!(interval.next(&[summary(80, true)], 20), MIN);
+            assert_eq!(interval.next(&[summary(25, false)], 20), MIN);
+        }
+    }
+
+    #[test]
+    fn maximum_below_the_minimum_is_raised_to_it() {
+        let mut interval = AdaptiveInterval::new(MAX, MIN);
+        for _ in 0..10 {
+            assert_eq!(interval.next(&[], 20), MAX);
+        }
+    }
+}
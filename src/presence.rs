@@ -0,0 +1,82 @@
+use std::sync::{Arc, Mutex};
+
+use log::{info, warn};
+use zbus::{connection, interface, Connection, Proxy};
+
+use crate::notifications;
+
+pub const BUS_NAME: &str = "org.rustyrazertray";
+pub const OBJECT_PATH: &str = "/org/rustyrazertray";
+const INTERFACE: &str = "org.rustyrazertray.Tray";
+
+/// (device name, percent, charging) for every device that reported a battery on the last poll.
+pub type BatteryEntry = (String, u8, bool);
+
+#[derive(Debug, Clone, Default)]
+pub struct BatteryCache(Arc<Mutex<Vec<BatteryEntry>>>);
+
+impl BatteryCache {
+    pub fn replace(&self, batteries: Vec<BatteryEntry>) {
+        *self.0.lock().unwrap() = batteries;
+    }
+
+    fn snapshot(&self) -> Vec<BatteryEntry> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+struct Presence {
+    cache: BatteryCache,
+}
+
+#[interface(name = "org.rustyrazertray.Tray")]
+impl Presence {
+    fn get_batteries(&self) -> Vec<BatteryEntry> {
+        self.cache.snapshot()
+    }
+
+    /// Called by a second launch, so double-starting the tray gives some visible feedback.
+    async fn already_running(&self) {
+        info!("Another instance was started; this one keeps running");
+        notifications::send(
+            "dialog-information",
+            "Rusty Razer Tray is already running",
+            "Look for its battery icon in the system tray.",
+        )
+        .await;
+    }
+}
+
+/// Tells the running instance that it was launched again. Fails if the running tray has no
+/// presence on the session bus, e.g. because it couldn't reach the bus itself.
+pub async fn signal_running_instance() -> zbus::Result<()> {
+    let connection = Connection::session().await?;
+    let proxy = Proxy::new(&connection, BUS_NAME, OBJECT_PATH, INTERFACE).await?;
+    proxy.call("AlreadyRunning", &()).await
+}
+
+/// Publishes the cached battery data on the session bus so scripts can query the running tray.
+/// Returns `None` (after logging) if the name is already owned or the bus is unavailable.
+pub async fn register(cache: BatteryCache) -> Option<Connection> {
+    let connection = connection::Builder::session()
+        .and_then(|builder| builder.serve_at(OBJECT_PATH, Presence { cache }))
+        .and_then(|builder| builder.name(BUS_NAME));
+    let connection = match connection {
+        Ok(builder) => builder.build().await,
+        Err(err) => Err(err),
+    };
+    match connection {
+        Ok(connection) => {
+            info!("Registered {BUS_NAME} on the session bus");
+            Some(connection)
+        }
+        Err(zbus::Error::NameTaken) => {
+            warn!("{BUS_NAME} is already owned on the session bus; skipping registration");
+            None
+        }
+        Err(err) => {
+            warn!("Failed to register {BUS_NAME} on the session bus: {err}");
+            None
+        }
+    }
+}
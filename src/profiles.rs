@@ -0,0 +1,79 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use log::{info, warn};
+use rusty_razer_tray::openrazer::{Manager, RazerError};
+
+use crate::config::{Config, LightingProfile, ProfileEffect};
+use crate::effects::{EffectStore, SavedEffect, SharedEffectStore};
+
+/// Applies `profile` to every device with an LED it names. Effects a device can't do are skipped
+/// with a warning rather than failing the rest of the profile. When `effects` is set, whatever
+/// was applied is recorded so it survives a replug.
+pub async fn apply(
+    manager: &Manager,
+    profile: &LightingProfile,
+    effects: Option<&SharedEffectStore>,
+) -> Result<(), RazerError> {
+    for path in manager.get_devices().await? {
+        let device = match manager.get_device(path).await {
+            Ok(device) => device,
+            Err(err) => {
+                warn!("Failed to open a device to apply a lighting profile: {err}");
+                continue;
+            }
+        };
+        let serial = match device.get_serial().await {
+            Ok(serial) => serial,
+            Err(_) => device.object_path().to_string(),
+        };
+        for (wanted, effect) in profile {
+            let Some((led, name)) = device
+                .supported_leds()
+                .iter()
+                .find(|(_, name)| name.eq_ignore_ascii_case(wanted))
+            else {
+                continue;
+            };
+            let (result, saved) = match *effect {
+                ProfileEffect::Static { color } => {
+                    (device.set_static(*led, color).await, SavedEffect::Static { color })
+                }
+                ProfileEffect::None => (device.set_none(*led).await, SavedEffect::None),
+            };
+            match result {
+                Ok(()) => {
+                    info!("Set {effect:?} on the {name} LED of {serial}");
+                    if let Some(effects) = effects {
+                        effects.lock().unwrap().record(&serial, name, saved);
+                    }
+                }
+                Err(RazerError::Unsupported { .. }) => {
+                    warn!("{serial} can't do {effect:?} on its {name} LED; skipping it")
+                }
+                Err(err) => warn!("Failed to set the {name} LED on {serial}: {err}"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Looks up `name` and applies it, for `--profile NAME`. Like the tray menu, it records what was
+/// applied when `lighting.persist_effects` is on.
+pub async fn apply_named(config: &Config, name: &str) -> Result<(), String> {
+    let profiles = &config.lighting.profiles;
+    let Some(profile) = profiles.get(name) else {
+        let known: Vec<_> = profiles.keys().map(String::as_str).collect();
+        if known.is_empty() {
+            return Err(format!("No lighting profile {name:?}; none are configured"));
+        }
+        return Err(format!("No lighting profile {name:?}; known: {}", known.join(", ")));
+    };
+    let mut manager = Manager::new().await.map_err(|err| err.to_string())?;
+    manager.set_call_timeout(Duration::from_millis(config.dbus.timeout_ms));
+    let effects = config
+        .lighting
+        .persist_effects
+        .then(|| Arc::new(Mutex::new(EffectStore::load())));
+    apply(&manager, profile, effects.as_ref()).await.map_err(|err| err.to_string())
+}
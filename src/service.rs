@@ -0,0 +1,54 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use crate::config::xdg_config_home;
+
+const UNIT_NAME: &str = "rusty-razer-tray.service";
+
+/// Writes a systemd user unit that runs the tray in the foreground so systemd owns the process.
+pub fn install_service(force: bool) -> io::Result<PathBuf> {
+    let unit_dir = xdg_config_home()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "HOME is not set"))?
+        .join("systemd/user");
+    fs::create_dir_all(&unit_dir)?;
+    let unit_path = unit_dir.join(UNIT_NAME);
+
+    let exe = env::current_exe()?;
+    let unit = format!(
+        "[Unit]\n\
+         Description=Razer device battery tray\n\
+         PartOf=graphical-session.target\n\
+         After=graphical-session.target\n\
+         \n\
+         [Service]\n\
+         ExecStart=\"{}\"\n\
+         Environment=RUSTY_RAZER_TRAY_FOREGROUND=1\n\
+         Restart=on-failure\n\
+         \n\
+         [Install]\n\
+         WantedBy=graphical-session.target\n",
+        exe.display()
+    );
+
+    let mut options = OpenOptions::new();
+    options.write(true);
+    if force {
+        options.create(true).truncate(true);
+    } else {
+        options.create_new(true);
+    }
+    let mut file = options.open(&unit_path).map_err(|err| {
+        if err.kind() == io::ErrorKind::AlreadyExists {
+            io::Error::new(
+                err.kind(),
+                format!("{} already exists, use --force to overwrite it", unit_path.display()),
+            )
+        } else {
+            err
+        }
+    })?;
+    file.write_all(unit.as_bytes())?;
+    Ok(unit_path)
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+/// Exponential moving average of battery readings, per device. Wireless mice report coarse,
+/// jumpy steps, and this hides the jitter at the cost of lagging behind real changes by
+/// roughly `window` polls.
+pub struct BatterySmoother {
+    alpha: f32,
+    averages: HashMap<String, f32>,
+}
+
+impl BatterySmoother {
+    /// A window of 0 or 1 disables smoothing.
+    pub fn new(window: u8) -> Self {
+        Self {
+            alpha: 2.0 / (f32::from(window.max(1)) + 1.0),
+            averages: HashMap::new(),
+        }
+    }
+
+    pub fn smooth(&mut self, device: &str, percent: u8) -> u8 {
+        let average = self
+            .averages
+            .entry(device.to_string())
+            .and_modify(|average| {
+                *average += self.alpha * (f32::from(percent) - *average);
+            })
+            .or_insert(f32::from(percent));
+        average.round().clamp(0.0, 100.0) as u8
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn window_of_zero_shows_raw_readings() {
+        let mut smoother = BatterySmoother::new(0);
+        assert_eq!(smoother.smooth("mouse", 80), 80);
+        assert_eq!(smoother.smooth("mouse", 60), 60);
+    }
+
+    #[test]
+    fn larger_windows_follow_changes_more_slowly() {
+        // A window of 4 moves 40% of the way to each new reading.
+        let mut smoother = BatterySmoother::new(4);
+        assert_eq!(smoother.smooth("mouse", 50), 50);
+        assert_eq!(smoother.smooth("mouse", 40), 46);
+        assert_eq!(smoother.smooth("mouse", 40), 44);
+
+        let mut slower = BatterySmoother::new(9);
+        slower.smooth("mouse", 50);
+        assert_eq!(slower.smooth("mouse", 40), 48);
+    }
+
+    #[test]
+    fn devices_are_averaged_separately() {
+        let mut smoother = BatterySmoother::new(4);
+        smoother.smooth("mouse", 50);
+        assert_eq!(smoother.smooth("keyboard", 90), 90);
+        assert_eq!(smoother.smooth("mouse", 50), 50);
+    }
+}
@@ -0,0 +1,117 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use log::{info, warn};
+use rusty_razer_tray::openrazer::{Device, LedId, RazerError, Rgb};
+
+use crate::config::TemperatureConfig;
+
+const HWMON_ROOT: &str = "/sys/class/hwmon";
+/// hwmon drivers that report the CPU package, in order of preference.
+const CPU_SENSORS: [&str; 5] = ["k10temp", "zenpower", "coretemp", "cpu_thermal", "acpitz"];
+
+/// Sets the configured devices to a static colour for the CPU temperature on every poll. Devices
+/// are only written when their colour changes, so a steady temperature costs one file read.
+pub struct TemperatureLighting {
+    config: TemperatureConfig,
+    shown: HashMap<String, Rgb>,
+    /// Devices without a plain static effect, skipped after the first warning.
+    unsupported: HashSet<String>,
+    sensor_missing: bool,
+}
+
+impl TemperatureLighting {
+    pub fn new(config: TemperatureConfig) -> Self {
+        Self {
+            config,
+            shown: HashMap::new(),
+            unsupported: HashSet::new(),
+            sensor_missing: false,
+        }
+    }
+
+    pub async fn apply(&mut self, devices: &[Arc<Device>]) {
+        if self.config.serials.is_empty() {
+            return;
+        }
+        let Some(celsius) = read_cpu_celsius(self.config.sensor.as_deref()) else {
+            if !self.sensor_missing {
+                warn!("No CPU temperature sensor found under {HWMON_ROOT}; not colouring devices");
+                self.sensor_missing = true;
+            }
+            return;
+        };
+        if self.sensor_missing {
+            info!("CPU temperature sensor is back; colouring devices again");
+            self.sensor_missing = false;
+        }
+        let color = gradient(celsius, self.config.cool, self.config.hot);
+        for serial in &self.config.serials {
+            if self.unsupported.contains(serial) || self.shown.get(serial) == Some(&color) {
+                continue;
+            }
+            // Not being listed just means the device is unplugged right now.
+            let Some(device) = devices
+                .iter()
+                .find(|device| device.serial_from_path() == Some(serial.as_str()))
+            else {
+                self.shown.remove(serial);
+                continue;
+            };
+            match device.set_static(LedId::Unspecified, color).await {
+                Ok(()) => {
+                    self.shown.insert(serial.clone(), color);
+                }
+                Err(RazerError::Unsupported { .. }) => {
+                    warn!("{serial} has no static lighting; leaving it out of temperature colours");
+                    self.unsupported.insert(serial.clone());
+                }
+                Err(err) => warn!("Failed to colour {serial} for the CPU temperature: {err}"),
+            }
+        }
+    }
+}
+
+/// Reads `temp1_input` of the named hwmon device, or of the first known CPU sensor, in °C.
+fn read_cpu_celsius(sensor: Option<&str>) -> Option<f64> {
+    let mut found: Vec<(String, f64)> = Vec::new();
+    for entry in fs::read_dir(HWMON_ROOT).ok()?.flatten() {
+        let dir = entry.path();
+        let Ok(name) = fs::read_to_string(dir.join("name")) else {
+            continue;
+        };
+        if let Some(celsius) = read_millidegrees(&dir.join("temp1_input")) {
+            found.push((name.trim().to_string(), celsius));
+        }
+    }
+    let pick = |wanted: &str| {
+        found.iter().find(|(name, _)| name == wanted).map(|(_, celsius)| *celsius)
+    };
+    match sensor {
+        Some(sensor) => pick(sensor),
+        None => CPU_SENSORS.into_iter().find_map(pick),
+    }
+}
+
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    let value: i64 = fs::read_to_string(path).ok()?.trim().parse().ok()?;
+    Some(value as f64 / 1000.0)
+}
+
+/// Green at or below `cool`, through yellow, to red at or above `hot`.
+fn gradient(celsius: f64, cool: f64, hot: f64) -> Rgb {
+    let heat = if hot > cool {
+        ((celsius - cool) / (hot - cool)).clamp(0.0, 1.0)
+    } else if celsius >= hot {
+        1.0
+    } else {
+        0.0
+    };
+    Rgb {
+        r: (heat * 2.0 * 255.0).min(255.0).round() as u8,
+        g: ((1.0 - heat) * 2.0 * 255.0).min(255.0).round() as u8,
+        b: 0,
+    }
+}
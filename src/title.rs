@@ -0,0 +1,92 @@
+use log::warn;
+
+/// A user-supplied tray title such as `"{name}: {percent}%{charging}"`, parsed once at startup.
+/// `{charging}` becomes a lightning bolt while charging and nothing otherwise.
+#[derive(Debug, Clone)]
+pub struct TitleTemplate {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Text(String),
+    Percent,
+    Name,
+    Charging,
+}
+
+impl TitleTemplate {
+    /// Unknown placeholders are kept as literal text, with a warning, so a typo shows up in the
+    /// title instead of silently vanishing.
+    pub fn parse(template: &str) -> Self {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let Some(len) = rest[start..].find('}') else {
+                break;
+            };
+            push_text(&mut parts, &rest[..start]);
+            let placeholder = &rest[start + 1..start + len];
+            match placeholder {
+                "percent" => parts.push(Part::Percent),
+                "name" => parts.push(Part::Name),
+                "charging" => parts.push(Part::Charging),
+                other => {
+                    warn!("Unknown placeholder {{{other}}} in the title template");
+                    push_text(&mut parts, &rest[start..=start + len]);
+                }
+            }
+            rest = &rest[start + len + 1..];
+        }
+        push_text(&mut parts, rest);
+        Self { parts }
+    }
+
+    pub fn render(&self, percent: u8, name: &str, charging: bool) -> String {
+        let mut title = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Text(text) => title.push_str(text),
+                Part::Percent => title.push_str(&percent.to_string()),
+                Part::Name => title.push_str(name),
+                Part::Charging if charging => title.push('⚡'),
+                Part::Charging => {}
+            }
+        }
+        title
+    }
+}
+
+fn push_text(parts: &mut Vec<Part>, text: &str) {
+    if text.is_empty() {
+        return;
+    }
+    match parts.last_mut() {
+        Some(Part::Text(previous)) => previous.push_str(text),
+        _ => parts.push(Part::Text(text.to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_in_every_placeholder() {
+        let template = TitleTemplate::parse("{name}: {percent}%{charging}");
+        assert_eq!(template.render(42, "Viper", false), "Viper: 42%");
+        assert_eq!(template.render(42, "Viper", true), "Viper: 42%⚡");
+    }
+
+    #[test]
+    fn keeps_unknown_placeholders_and_stray_braces_as_text() {
+        let template = TitleTemplate::parse("{nmae} {percent}% {");
+        assert_eq!(template.render(7, "Viper", false), "{nmae} 7% {");
+    }
+
+    #[test]
+    fn text_around_placeholders_is_merged() {
+        let template = TitleTemplate::parse("a{bogus}b");
+        assert_eq!(template.parts, [Part::Text("a{bogus}b".to_string())]);
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::{Duration, Instant};
+
+/// How long a device has to stay plugged in or unplugged before the icon follows, so a loose
+/// cable or a charger flicking off at 100% doesn't make the icon blink in and out.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+
+/// Decides whether the tray icon should be shown when `battery.only_on_battery` is set.
+#[derive(Default)]
+pub struct VisibilityDebounce {
+    visible: Option<bool>,
+    pending_since: Option<Instant>,
+}
+
+impl VisibilityDebounce {
+    /// Returns the visibility to use now. The first reading is taken as is; after that a change
+    /// only sticks once it has been wanted for `SETTLE_TIME`.
+    pub fn update(&mut self, wanted: bool, now: Instant) -> bool {
+        let Some(visible) = self.visible else {
+            self.visible = Some(wanted);
+            return wanted;
+        };
+        if wanted == visible {
+            self.pending_since = None;
+            return visible;
+        }
+        let since = *self.pending_since.get_or_insert(now);
+        if now.duration_since(since) < SETTLE_TIME {
+            return visible;
+        }
+        self.visible = Some(wanted);
+        self.pending_since = None;
+        wanted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_reading_applies_straight_away() {
+        let mut debounce = VisibilityDebounce::default();
+        assert!(!debounce.update(false, Instant::now()));
+    }
+
+    #[test]
+    fn changes_stick_only_after_settling() {
+        let mut debounce = VisibilityDebounce::default();
+        let start = Instant::now();
+        assert!(debounce.update(true, start));
+        assert!(debounce.update(false, start + Duration::from_secs(1)));
+        assert!(debounce.update(false, start + Duration::from_secs(4)));
+        assert!(!debounce.update(false, start + Duration::from_secs(1) + SETTLE_TIME));
+    }
+
+    #[test]
+    fn flicker_back_restarts_the_wait() {
+        let mut debounce = VisibilityDebounce::default();
+        let start = Instant::now();
+        debounce.update(true, start);
+        debounce.update(false, start + Duration::from_secs(1));
+        assert!(debounce.update(true, start + Duration::from_secs(2)));
+        assert!(debounce.update(false, start + Duration::from_secs(3)));
+        assert!(debounce.update(false, start + Duration::from_secs(7)));
+        assert!(!debounce.update(false, start + Duration::from_secs(3) + SETTLE_TIME));
+    }
+}
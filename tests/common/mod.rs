@@ -0,0 +1,204 @@
+//! A fake OpenRazer device served over a peer-to-peer D-Bus connection, so tests don't need a
+//! bus daemon or real hardware. Add more fake interfaces next to `FakePower` and serve them with
+//! `MockDevice::with`.
+
+use rusty_razer_tray::openrazer::{BusKind, Device, Manager, OPENRAZER_ROOT_PATH};
+use tokio::net::UnixStream;
+use zbus::connection::Builder;
+use zbus::zvariant::OwnedObjectPath;
+use zbus::{interface, Connection, Guid, Interface};
+
+#[derive(Default)]
+pub struct FakePower {
+    pub battery: f64,
+    pub charging: bool,
+    /// How many times `getBattery` has been called.
+    pub battery_reads: u32,
+}
+
+#[interface(name = "razer.device.power")]
+impl FakePower {
+    #[zbus(name = "getBattery")]
+    fn get_battery(&mut self) -> f64 {
+        self.battery_reads += 1;
+        self.battery
+    }
+
+    #[zbus(name = "isCharging")]
+    fn is_charging(&self) -> bool {
+        self.charging
+    }
+}
+
+pub struct FakeMisc {
+    pub serial: String,
+}
+
+#[interface(name = "razer.device.misc")]
+impl FakeMisc {
+    #[zbus(name = "getSerial")]
+    fn get_serial(&self) -> String {
+        self.serial.clone()
+    }
+}
+
+/// A daemon version whose `getBattery` replies with an integer instead of a double.
+#[cfg(feature = "battery")]
+pub struct FakeIntegerPower {
+    pub battery: i32,
+}
+
+#[cfg(feature = "battery")]
+#[interface(name = "razer.device.power")]
+impl FakeIntegerPower {
+    #[zbus(name = "getBattery")]
+    fn get_battery(&self) -> i32 {
+        self.battery
+    }
+}
+
+#[cfg(feature = "dpi")]
+#[derive(Default)]
+pub struct FakeDpi {
+    /// Returned as-is from `getDPI`, so tests can send malformed replies.
+    pub dpi: Vec<i32>,
+}
+
+#[cfg(feature = "dpi")]
+#[interface(name = "razer.device.dpi")]
+impl FakeDpi {
+    #[zbus(name = "getDPI")]
+    fn get_dpi(&self) -> Vec<i32> {
+        self.dpi.clone()
+    }
+}
+
+/// Chroma lighting with whole-device custom mode but no per-key rows.
+#[cfg(feature = "lighting")]
+pub struct FakeChroma;
+
+#[cfg(feature = "lighting")]
+#[interface(name = "razer.device.lighting.chroma")]
+impl FakeChroma {
+    #[zbus(name = "setCustom")]
+    fn set_custom(&self) {}
+}
+
+/// Chroma lighting with per-key rows, which records every row it is sent.
+#[cfg(feature = "lighting")]
+#[derive(Default)]
+pub struct FakeMatrix {
+    /// The raw `setKeyRow` payloads, in the order they arrived.
+    pub rows: Vec<Vec<u8>>,
+    /// How many times `setCustom` has been called.
+    pub custom_calls: u32,
+}
+
+#[cfg(feature = "lighting")]
+#[interface(name = "razer.device.lighting.chroma")]
+impl FakeMatrix {
+    #[zbus(name = "setKeyRow")]
+    fn set_key_row(&mut self, data: Vec<u8>) {
+        self.rows.push(data);
+    }
+
+    #[zbus(name = "setCustom")]
+    fn set_custom(&mut self) {
+        self.custom_calls += 1;
+    }
+}
+
+/// The misc interface of a per-key keyboard, which knows its matrix size.
+#[cfg(feature = "lighting")]
+pub struct FakeMatrixMisc {
+    pub rows: i32,
+    pub columns: i32,
+    /// How many `getDeviceMode` reads still answer normal mode, as on firmware that drops the
+    /// first `setCustom`. Driver mode is reported after that.
+    pub dropped: u32,
+}
+
+#[cfg(feature = "lighting")]
+#[interface(name = "razer.device.misc")]
+impl FakeMatrixMisc {
+    #[zbus(name = "getMatrixDimensions")]
+    fn get_matrix_dimensions(&self) -> Vec<i32> {
+        vec![self.rows, self.columns]
+    }
+
+    #[zbus(name = "getDeviceMode")]
+    fn get_device_mode(&mut self) -> String {
+        if self.dropped > 0 {
+            self.dropped -= 1;
+            return "0:0".to_string();
+        }
+        "3:0".to_string()
+    }
+}
+
+/// Only the red profile LED, as on keyboards with a single macro indicator.
+#[cfg(feature = "lighting")]
+#[derive(Default)]
+pub struct FakeProfileLed {
+    pub red: bool,
+}
+
+#[cfg(feature = "lighting")]
+#[interface(name = "razer.device.lighting.profile_led")]
+impl FakeProfileLed {
+    #[zbus(name = "getRedLED")]
+    fn get_red_led(&self) -> bool {
+        self.red
+    }
+
+    #[zbus(name = "setRedLED")]
+    fn set_red_led(&mut self, on: bool) {
+        self.red = on;
+    }
+}
+
+pub struct MockDevice {
+    path: OwnedObjectPath,
+    server: Builder<'static>,
+    client: UnixStream,
+}
+
+impl MockDevice {
+    pub fn new(serial: &str) -> Self {
+        let (server, client) = UnixStream::pair().expect("failed to create socket pair");
+        let guid = Guid::generate();
+        Self {
+            path: OwnedObjectPath::try_from(format!("{OPENRAZER_ROOT_PATH}/device/{serial}"))
+                .expect("invalid serial"),
+            server: Builder::unix_stream(server).server(guid).unwrap().p2p(),
+            client,
+        }
+    }
+
+    pub fn with<I: Interface>(mut self, iface: I) -> Self {
+        self.server = self.server.serve_at(self.path.clone(), iface).unwrap();
+        self
+    }
+
+    /// Connects both ends and returns the client-side `Device` along with the server connection,
+    /// which must be kept alive for the duration of the test.
+    pub async fn connect(self) -> (Device, Connection) {
+        let (manager, path, server) = self.connect_manager().await;
+        let device = Device::new(manager.connection().clone(), path)
+            .await
+            .expect("failed to introspect mock device");
+        (device, server)
+    }
+
+    /// Like `connect`, but hands back a `Manager` on the client connection and the device's path,
+    /// for tests of what the manager shares between the devices it opens.
+    pub async fn connect_manager(self) -> (Manager, OwnedObjectPath, Connection) {
+        let (server, client) = tokio::join!(
+            self.server.build(),
+            Builder::unix_stream(self.client).p2p().build(),
+        );
+        let server = server.expect("failed to start mock device");
+        let client = client.expect("failed to connect to mock device");
+        (Manager::from_connection(client, BusKind::Session), self.path, server)
+    }
+}
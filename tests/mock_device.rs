@@ -0,0 +1,281 @@
+mod common;
+
+#[cfg(feature = "dpi")]
+use common::FakeDpi;
+#[cfg(feature = "lighting")]
+use common::{FakeChroma, FakeMatrix, FakeMatrixMisc, FakeProfileLed};
+#[cfg(feature = "battery")]
+use common::FakeIntegerPower;
+use common::{FakeMisc, FakePower, MockDevice};
+#[cfg(feature = "lighting")]
+use rusty_razer_tray::openrazer::{Frame, LedId, MatrixDimensions, RazerError, Rgb};
+
+#[tokio::test]
+#[cfg(feature = "battery")]
+async fn reads_battery_percent_from_power_interface() {
+    let (device, _server) = MockDevice::new("PM0000000000001")
+        .with(FakePower {
+            battery: 87.5,
+            charging: true,
+            ..Default::default()
+        })
+        .connect()
+        .await;
+
+    assert!(device.has_feature("battery"));
+    assert_eq!(device.get_battery_percent().await.unwrap(), 87.5);
+    assert!(device.is_charging().await.unwrap());
+}
+
+#[tokio::test]
+#[cfg(feature = "battery")]
+async fn reads_integer_battery_replies() {
+    let (device, _server) = MockDevice::new("PM0000000000010")
+        .with(FakeIntegerPower { battery: 64 })
+        .connect()
+        .await;
+
+    assert_eq!(device.get_battery_percent().await.unwrap(), 64.0);
+}
+
+#[tokio::test]
+#[cfg(feature = "dpi")]
+async fn missing_interfaces_are_not_advertised() {
+    let (device, _server) = MockDevice::new("PM0000000000002")
+        .with(FakePower {
+            battery: 10.0,
+            charging: false,
+            ..Default::default()
+        })
+        .connect()
+        .await;
+
+    assert!(!device.has_feature("dpi"));
+    assert!(device.get_dpi().await.is_err());
+}
+
+#[tokio::test]
+#[cfg(feature = "battery")]
+async fn battery_reads_are_cached_until_forced() {
+    let (device, server) = MockDevice::new("PM0000000000003")
+        .with(FakePower {
+            battery: 50.0,
+            ..Default::default()
+        })
+        .connect()
+        .await;
+    let reads = || async {
+        let power = server
+            .object_server()
+            .interface::<_, FakePower>(device.object_path())
+            .await
+            .unwrap();
+        power.get().await.battery_reads
+    };
+
+    assert_eq!(device.get_battery_percent().await.unwrap(), 50.0);
+    assert_eq!(device.get_battery_percent().await.unwrap(), 50.0);
+    assert_eq!(reads().await, 1);
+
+    assert_eq!(device.refresh_battery_percent().await.unwrap(), 50.0);
+    assert_eq!(reads().await, 2);
+}
+
+#[tokio::test]
+#[cfg(feature = "battery")]
+async fn devices_from_one_manager_share_battery_reads() {
+    let (manager, path, server) = MockDevice::new("PM0000000000014")
+        .with(FakePower {
+            battery: 42.0,
+            ..Default::default()
+        })
+        .connect_manager()
+        .await;
+    let tray = manager.get_device(path.clone()).await.unwrap();
+    let tooltip = manager.get_device(path.clone()).await.unwrap();
+
+    assert_eq!(tray.get_battery_percent().await.unwrap(), 42.0);
+    assert_eq!(tooltip.get_battery_percent().await.unwrap(), 42.0);
+    let power = server
+        .object_server()
+        .interface::<_, FakePower>(path)
+        .await
+        .unwrap();
+    assert_eq!(power.get().await.battery_reads, 1);
+}
+
+#[tokio::test]
+#[cfg(feature = "dpi")]
+async fn dpi_ignores_trailing_values() {
+    let (device, _server) = MockDevice::new("PM0000000000004")
+        .with(FakeDpi {
+            dpi: vec![1600, 800, 3],
+        })
+        .connect()
+        .await;
+
+    let dpi = device.get_dpi().await.unwrap();
+    assert_eq!((dpi.dpi_x, dpi.dpi_y), (1600, 800));
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn custom_mode_alone_is_not_a_per_key_matrix() {
+    let (device, _server) = MockDevice::new("PM0000000000005")
+        .with(FakeChroma)
+        .connect()
+        .await;
+
+    assert!(device.has_feature("custom_frame"));
+    assert!(!device.has_feature("per_key_matrix"));
+    let err = device.fill_matrix(Rgb { r: 255, g: 0, b: 0 }).await.unwrap_err();
+    assert!(matches!(err, RazerError::Unsupported { .. }));
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn frames_of_different_sizes_are_not_diffed() {
+    let (device, server) = MockDevice::new("PM0000000000015")
+        .with(FakeMatrix::default())
+        .with(FakeMatrixMisc {
+            rows: 6,
+            columns: 22,
+            dropped: 0,
+        })
+        .connect()
+        .await;
+
+    let prev = Frame::new(MatrixDimensions { rows: 6, columns: 22 });
+    let next = Frame::new(MatrixDimensions { rows: 6, columns: 0 });
+    let err = device.set_matrix_diff(&prev, &next).await.unwrap_err();
+    assert!(matches!(err, RazerError::FrameSizeMismatch { .. }));
+
+    let chroma = server
+        .object_server()
+        .interface::<_, FakeMatrix>(device.object_path())
+        .await
+        .unwrap();
+    assert!(chroma.get().await.rows.is_empty());
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn set_custom_is_reissued_only_when_the_device_dropped_it() {
+    let red = Rgb { r: 255, g: 0, b: 0 };
+    for (serial, dropped, expected) in [("PM0000000000016", 0, 1), ("PM0000000000017", 1, 2)] {
+        let (device, server) = MockDevice::new(serial)
+            .with(FakeMatrix::default())
+            .with(FakeMatrixMisc {
+                rows: 6,
+                columns: 22,
+                dropped,
+            })
+            .connect()
+            .await;
+
+        device.fill_matrix(red).await.unwrap();
+        let chroma = server
+            .object_server()
+            .interface::<_, FakeMatrix>(device.object_path())
+            .await
+            .unwrap();
+        let chroma = chroma.get().await;
+        assert_eq!(chroma.rows.len(), 6);
+        assert_eq!(chroma.custom_calls, expected, "{dropped} dropped");
+    }
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn features_are_listed_sorted() {
+    let serial = "PM0000000000011";
+    let (device, _server) = MockDevice::new(serial)
+        .with(FakePower::default())
+        .with(FakeChroma)
+        .with(FakeMisc { serial: serial.to_string() })
+        .connect()
+        .await;
+
+    assert_eq!(device.features(), ["battery", "custom_frame"]);
+}
+
+#[tokio::test]
+async fn raw_introspection_is_the_unparsed_reply() {
+    let serial = "PM0000000000012";
+    let (device, _server) = MockDevice::new(serial)
+        .with(FakeMisc { serial: serial.to_string() })
+        .connect()
+        .await;
+
+    let xml = device.raw_introspection().await.unwrap();
+    assert!(xml.contains("<!DOCTYPE node"), "{xml}");
+    assert!(xml.contains(r#"<interface name="razer.device.misc">"#), "{xml}");
+    assert!(xml.contains(r#"<method name="getSerial">"#), "{xml}");
+}
+
+#[tokio::test]
+async fn responsive_only_when_the_device_answers() {
+    let serial = "PM0000000000006";
+    let (device, _server) = MockDevice::new(serial)
+        .with(FakeMisc { serial: serial.to_string() })
+        .connect()
+        .await;
+    assert!(device.is_responsive().await);
+
+    let (ghost, _server) = MockDevice::new("PM0000000000007")
+        .with(FakePower::default())
+        .connect()
+        .await;
+    assert!(!ghost.is_responsive().await);
+}
+
+#[tokio::test]
+#[cfg(feature = "battery")]
+async fn empty_battery_without_charging_means_the_dongle_lost_the_device() {
+    let (device, _server) = MockDevice::new("PM0000000000008")
+        .with(FakePower::default())
+        .connect()
+        .await;
+    assert!(!device.dongle_connected().await.unwrap());
+
+    let (device, _server) = MockDevice::new("PM0000000000009")
+        .with(FakePower {
+            charging: true,
+            ..Default::default()
+        })
+        .connect()
+        .await;
+    assert!(device.dongle_connected().await.unwrap());
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn profile_leds_are_guarded_one_by_one() {
+    let (device, _server) = MockDevice::new("PM0000000000013")
+        .with(FakeProfileLed::default())
+        .connect()
+        .await;
+
+    assert!(!device.get_profile_led(LedId::KeymapRedLED).await.unwrap());
+    device.set_profile_led(LedId::KeymapRedLED, true).await.unwrap();
+    assert!(device.get_profile_led(LedId::KeymapRedLED).await.unwrap());
+
+    let err = device.set_profile_led(LedId::KeymapGreenLED, true).await.unwrap_err();
+    assert!(matches!(err, RazerError::Unsupported { .. }));
+    let err = device.set_profile_led(LedId::LogoLED, true).await.unwrap_err();
+    assert!(matches!(err, RazerError::Unsupported { .. }));
+}
+
+#[tokio::test]
+#[cfg(feature = "lighting")]
+async fn profile_leds_take_no_static_colour() {
+    let (device, _server) = MockDevice::new("PM0000000000018")
+        .with(FakeProfileLed::default())
+        .connect()
+        .await;
+
+    let red = Rgb { r: 255, g: 0, b: 0 };
+    let err = device.set_static(LedId::KeymapRedLED, red).await.unwrap_err();
+    assert!(matches!(err, RazerError::Unsupported { .. }));
+    assert!(!device.get_profile_led(LedId::KeymapRedLED).await.unwrap());
+}